@@ -0,0 +1,206 @@
+use crate::components::cartridge::{self, CartridgeHeader};
+use crate::utils::hardware_identification::{
+    cartridge_type_decoder, destination_decoder, ram_size_decoder, rom_size_decoder,
+};
+use serde_json::{Value, json};
+use std::fs;
+
+const USAGE: &str = "Usage: gameboy info [--json | --format human|json] <rom...>";
+
+#[derive(PartialEq)]
+enum Format {
+    Human,
+    Json,
+}
+
+/// `gameboy info`: prints the header of each ROM without booting it. Returns the process
+/// exit code, non-zero if any ROM could not be read or parsed.
+pub fn run(args: &[String]) -> i32 {
+    let mut format = Format::Human;
+    let mut paths = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--json" => format = Format::Json,
+            "--format" => match args.next().map(String::as_str) {
+                Some("human") => format = Format::Human,
+                Some("json") => format = Format::Json,
+                _ => {
+                    eprintln!("{USAGE}");
+                    return 2;
+                }
+            },
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return 0;
+            }
+            _ => paths.push(arg.as_str()),
+        }
+    }
+
+    if paths.is_empty() {
+        eprintln!("{USAGE}");
+        return 2;
+    }
+
+    let reports: Vec<Report> = paths.iter().map(|path| Report::read(path)).collect();
+    let failed = reports.iter().any(|report| report.header.is_err());
+
+    if format == Format::Json {
+        let json: Vec<Value> = reports.iter().map(Report::to_json).collect();
+        println!("{}", serde_json::to_string_pretty(&json).unwrap());
+    } else {
+        for (index, report) in reports.iter().enumerate() {
+            if index > 0 {
+                println!();
+            }
+            report.print();
+        }
+    }
+
+    if failed { 1 } else { 0 }
+}
+
+struct Report<'a> {
+    path: &'a str,
+    file_size: usize,
+    header: Result<CartridgeHeader, String>,
+}
+
+impl<'a> Report<'a> {
+    fn read(path: &'a str) -> Self {
+        match fs::read(path) {
+            Ok(rom) => Report {
+                path,
+                file_size: rom.len(),
+                header: CartridgeHeader::parse(&rom).map_err(|error| error.to_string()),
+            },
+            Err(error) => Report {
+                path,
+                file_size: 0,
+                header: Err(error.to_string()),
+            },
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        let header = match &self.header {
+            Ok(header) => header,
+            Err(error) => return json!({ "path": self.path, "error": error }),
+        };
+
+        let declared_size = header.declared_rom_size();
+        json!({
+            "path": self.path,
+            "title": header.title,
+            "manufacturer_code": header.manufacturer_code,
+            "cgb": header.cgb_support(),
+            "sgb": header.supports_sgb(),
+            "cartridge_type": {
+                "code": header.cartridge_type,
+                "name": cartridge_type_decoder(header.cartridge_type),
+                "supported": cartridge::is_supported(header.cartridge_type),
+            },
+            "rom_size": {
+                "code": header.rom_size,
+                "name": rom_size_decoder(header.rom_size),
+                "declared_bytes": declared_size,
+                "file_bytes": self.file_size,
+                "matches": declared_size == Some(self.file_size),
+            },
+            "ram_size": {
+                "code": header.ram_size,
+                "name": ram_size_decoder(header.ram_size),
+            },
+            "destination": {
+                "code": header.destination_code,
+                "name": destination_decoder(header.destination_code),
+            },
+            "licensee": {
+                "old_code": header.old_licensee_code,
+                "new_code": header.new_licensee_code,
+                "name": header.licensee,
+            },
+            "version": header.version,
+            "header_checksum": {
+                "stored": header.header_checksum,
+                "computed": header.computed_header_checksum,
+                "valid": header.header_checksum_valid(),
+            },
+            "global_checksum": {
+                "stored": header.global_checksum,
+                "computed": header.computed_global_checksum,
+                "valid": header.global_checksum_valid(),
+            },
+        })
+    }
+
+    fn print(&self) {
+        println!("{}", self.path);
+        let header = match &self.header {
+            Ok(header) => header,
+            Err(error) => {
+                println!("  Error: {error}");
+                return;
+            }
+        };
+
+        let supported = if cartridge::is_supported(header.cartridge_type) {
+            "supported"
+        } else {
+            "not supported"
+        };
+        let declared_size = header.declared_rom_size();
+
+        println!("  Title: {}", header.title);
+        println!("  Manufacturer code: {}", header.manufacturer_code);
+        println!("  CGB: {}", header.cgb_support());
+        println!(
+            "  SGB: {}",
+            if header.supports_sgb() { "yes" } else { "no" }
+        );
+        println!(
+            "  Cartridge type: {:#04X} {} ({supported})",
+            header.cartridge_type,
+            cartridge_type_decoder(header.cartridge_type)
+        );
+        println!(
+            "  ROM size: {:#04X} {}",
+            header.rom_size,
+            rom_size_decoder(header.rom_size)
+        );
+        println!(
+            "  File size: {} bytes, declared {} ({})",
+            self.file_size,
+            match declared_size {
+                Some(bytes) => format!("{bytes} bytes"),
+                None => "unknown".to_string(),
+            },
+            status(declared_size == Some(self.file_size))
+        );
+        println!("  RAM size: {}", ram_size_decoder(header.ram_size));
+        println!(
+            "  Destination: {}",
+            destination_decoder(header.destination_code)
+        );
+        println!("  Licensee: {}", header.licensee);
+        println!("  Version: {}", header.version);
+        println!(
+            "  Header checksum: stored {:#04X}, computed {:#04X} ({})",
+            header.header_checksum,
+            header.computed_header_checksum,
+            status(header.header_checksum_valid())
+        );
+        println!(
+            "  Global checksum: stored {:#06X}, computed {:#06X} ({})",
+            header.global_checksum,
+            header.computed_global_checksum,
+            status(header.global_checksum_valid())
+        );
+    }
+}
+
+fn status(ok: bool) -> &'static str {
+    if ok { "ok" } else { "mismatch" }
+}
@@ -0,0 +1,181 @@
+use crate::components::cartridge::CameraSource;
+use crate::components::gameboy::Gameboy;
+use crate::components::linked_pair::LinkedPair;
+use crate::io::camera_source;
+use crate::io::movie::Movie;
+use std::fs;
+use std::path::Path;
+
+const USAGE: &str = "Usage: gameboy play [--check] <rom> <movie> [--link <rom> <movie>]\n                    [--camera <png or directory>] [--expect <frame sha1>]...";
+
+/// `gameboy play`: replays a movie without a window and prints a hash of the last frame,
+/// so CI can compare it against a known good run. `--check` plays it twice instead and
+/// fails if any frame differs between the runs. `--link` plays a second movie on another
+/// Game Boy connected by link cable; `--expect` then checks the first and second frame.
+pub fn run(args: &[String]) -> i32 {
+    let mut paths = Vec::new();
+    let mut expect = Vec::new();
+    let mut check = false;
+    let mut camera = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--expect" => match args.next() {
+                Some(hash) => expect.push(hash.to_ascii_lowercase()),
+                None => {
+                    eprintln!("{USAGE}");
+                    return 2;
+                }
+            },
+            "--link" => match (args.next(), args.next()) {
+                (Some(rom_path), Some(movie_path)) => paths.extend([rom_path.as_str(), movie_path]),
+                _ => {
+                    eprintln!("{USAGE}");
+                    return 2;
+                }
+            },
+            "--camera" => match args.next().map(|path| camera_source::load(Path::new(path))) {
+                Some(Ok(source)) => camera = Some(source),
+                Some(Err(error)) => {
+                    eprintln!("Failed to load camera pictures: {error}");
+                    return 1;
+                }
+                None => {
+                    eprintln!("{USAGE}");
+                    return 2;
+                }
+            },
+            "--check" => check = true,
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return 0;
+            }
+            _ => paths.push(arg.as_str()),
+        }
+    }
+
+    let hashes = match paths[..] {
+        [rom_path, movie_path] => {
+            let (mut gameboy, movie) = match load(rom_path, movie_path, &camera) {
+                Ok(loaded) => loaded,
+                Err(error) => {
+                    eprintln!("{error}");
+                    return 1;
+                }
+            };
+            if check {
+                return check_movie(&movie, &mut gameboy);
+            }
+            if let Err(error) = movie.play(&mut gameboy, |_| ()) {
+                eprintln!("{error}");
+                return 1;
+            }
+            let hash = frame_sha1(gameboy.framebuffer());
+            println!("Played {} frames, last frame {hash}", movie.inputs.len());
+            vec![hash]
+        }
+        [first_rom, first_movie, second_rom, second_movie] if !check => {
+            match play_linked(
+                [(first_rom, first_movie), (second_rom, second_movie)],
+                &camera,
+            ) {
+                Ok(hashes) => hashes,
+                Err(error) => {
+                    eprintln!("{error}");
+                    return 1;
+                }
+            }
+        }
+        _ => {
+            eprintln!("{USAGE}");
+            return 2;
+        }
+    };
+
+    let mut failed = false;
+    for (hash, expected) in hashes.iter().zip(&expect) {
+        if expected != hash {
+            eprintln!("Expected last frame {expected}");
+            failed = true;
+        }
+    }
+    failed as i32
+}
+
+fn load(
+    rom_path: &str,
+    movie_path: &str,
+    camera: &Option<CameraSource>,
+) -> Result<(Gameboy, Movie), String> {
+    let data =
+        fs::read(movie_path).map_err(|error| format!("Failed to read {movie_path}: {error}"))?;
+    let movie = Movie::from_bytes(&data).map_err(|error| format!("{movie_path}: {error}"))?;
+
+    let mut gameboy = Gameboy::new();
+    if let Some(source) = camera {
+        gameboy.set_camera_source(source.clone());
+    }
+    gameboy
+        .cartridge_to_rom(rom_path.to_string())
+        .map_err(|error| format!("Failed to load {rom_path}: {error}"))?;
+    Ok((gameboy, movie))
+}
+
+fn check_movie(movie: &Movie, gameboy: &mut Gameboy) -> i32 {
+    match movie.check(gameboy) {
+        Ok(None) => {
+            println!("{} frames played identically twice", movie.inputs.len());
+            0
+        }
+        Ok(Some(frame)) => {
+            eprintln!("Runs diverged at frame {frame}");
+            1
+        }
+        Err(error) => {
+            eprintln!("{error}");
+            1
+        }
+    }
+}
+
+/// Plays both movies until the longer one ends; the other side lets go of all buttons.
+fn play_linked(
+    sides: [(&str, &str); 2],
+    camera: &Option<CameraSource>,
+) -> Result<Vec<String>, String> {
+    let [(first_gameboy, first_movie), (second_gameboy, second_movie)] = [
+        load(sides[0].0, sides[0].1, camera)?,
+        load(sides[1].0, sides[1].1, camera)?,
+    ];
+    let movies = [first_movie, second_movie];
+
+    let mut pair = LinkedPair::new(first_gameboy, second_gameboy);
+    for (gameboy, movie) in pair.gameboys_mut().iter_mut().zip(&movies) {
+        movie.begin(gameboy).map_err(|error| error.to_string())?;
+    }
+
+    let frames = movies
+        .iter()
+        .map(|movie| movie.inputs.len())
+        .max()
+        .unwrap_or(0);
+    for frame in 0..frames {
+        pair.write_inputs(
+            movies
+                .each_ref()
+                .map(|movie| movie.inputs.get(frame).copied().unwrap_or(0xFF)),
+        );
+        pair.run_frame();
+    }
+    let hashes = pair.framebuffers().map(frame_sha1);
+    println!(
+        "Played {frames} linked frames, last frames {} {}",
+        hashes[0], hashes[1]
+    );
+    Ok(hashes.to_vec())
+}
+
+fn frame_sha1(framebuffer: &[u8]) -> String {
+    sha1_smol::Sha1::from(framebuffer).digest().to_string()
+}
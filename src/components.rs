@@ -1,6 +1,11 @@
+mod bus;
+pub mod cartridge;
+pub mod cheats;
 mod cpu;
 pub mod gameboy;
+pub mod linked_pair;
 mod memory;
 mod ppu;
+pub mod ram_search;
 mod registers;
-mod apu;
+pub(crate) mod apu;
@@ -1,11 +1,12 @@
 use crate::components::memory::Memory;
+use crate::io::savestate::{StateError, StateReader, StateWriter};
 use blip_buf::BlipBuf;
 
 const WAVE_PATTERN : [[i32; 8]; 4] = [[-1,-1,-1,-1,1,-1,-1,-1],[-1,-1,-1,-1,1,1,-1,-1],[-1,-1,1,1,1,1,-1,-1],[1,1,1,1,-1,-1,1,1]];
 const CLOCK_RATE : u32 = 4_194_304;
 const CLOCK_PER_FRAME: u32 = CLOCK_RATE / 512;
 const OUTPUT_SAMPLE_COUNT : usize = 2_000;
-const SAMPLE_RATE : u32 = 44_100;
+pub(crate) const SAMPLE_RATE : u32 = 44_100;
 
 struct VolumeEnvelope {
     period: u8,
@@ -25,6 +26,23 @@ impl VolumeEnvelope {
             volume: 0,
         }
     }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.period);
+        state.write_bool(self.goes_up);
+        state.write_u8(self.delay);
+        state.write_u8(self.initial_volume);
+        state.write_u8(self.volume);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.period = state.read_u8()?;
+        self.goes_up = state.read_bool()?;
+        self.delay = state.read_u8()?;
+        self.initial_volume = state.read_u8()?;
+        self.volume = state.read_u8()?;
+        Ok(())
+    }
     
     fn get(&self, address: u16) -> u8 {
         match address {
@@ -81,6 +99,17 @@ impl LengthTimer {
             max,
         }
     }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.enabled);
+        state.write_u16(self.value);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.enabled = state.read_bool()?;
+        self.value = state.read_u16()?;
+        Ok(())
+    }
     
     fn is_active(&self) -> bool {
         self.value > 0
@@ -134,7 +163,8 @@ pub struct SquareWave {
 
 impl SquareWave {
     fn new() -> Self {
-        let mut buffer = BlipBuf::new((OUTPUT_SAMPLE_COUNT + 1) as u32);
+        // Room to spare for when the output rate is raised by rate control
+        let mut buffer = BlipBuf::new((OUTPUT_SAMPLE_COUNT * 2) as u32);
         buffer.set_rates(CLOCK_RATE as f64, SAMPLE_RATE as f64);
 
         SquareWave {
@@ -152,6 +182,35 @@ impl SquareWave {
         }
     }
 
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.enabled);
+        state.write_bool(self.dac_enabled);
+        state.write_u8(self.duty);
+        state.write_u8(self.phase);
+        self.length_timer.save_state(state);
+        self.volume_envelope.save_state(state);
+        state.write_u16(self.frequency);
+        state.write_u32(self.period);
+        state.write_i32(self.last_amp);
+        state.write_u32(self.delay);
+    }
+
+    /// Samples already in the blip buffer belong to the timeline being replaced, so they are dropped.
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.enabled = state.read_bool()?;
+        self.dac_enabled = state.read_bool()?;
+        self.duty = state.read_u8()?;
+        self.phase = state.read_u8()?;
+        self.length_timer.load_state(state)?;
+        self.volume_envelope.load_state(state)?;
+        self.frequency = state.read_u16()?;
+        self.period = state.read_u32()?;
+        self.last_amp = state.read_i32()?;
+        self.delay = state.read_u32()?;
+        self.buffer.clear();
+        Ok(())
+    }
+
     fn handle_nr21(&mut self, value: u8) {
         self.duty = value >> 6;
         self.length_timer.set(value & 0x3F);
@@ -266,6 +325,38 @@ impl APU {
             reg_ff25: 0x00,
         }
     }
+
+    /// Lets the front end stretch or squeeze the output slightly to match the audio device.
+    pub(crate) fn set_output_rate(&mut self, sample_rate: f64) {
+        self.channel2.buffer.set_rates(CLOCK_RATE as f64, sample_rate);
+    }
+
+    pub(crate) fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.enabled);
+        state.write_u32(self.time);
+        state.write_u32(self.prev_time);
+        state.write_u32(self.next_time);
+        state.write_u8(self.frame_step);
+        self.channel2.save_state(state);
+        state.write_u8(self.volume_left);
+        state.write_u8(self.volume_right);
+        state.write_u8(self.reg_vin_to_so);
+        state.write_u8(self.reg_ff25);
+    }
+
+    pub(crate) fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.enabled = state.read_bool()?;
+        self.time = state.read_u32()?;
+        self.prev_time = state.read_u32()?;
+        self.next_time = state.read_u32()?;
+        self.frame_step = state.read_u8()?;
+        self.channel2.load_state(state)?;
+        self.volume_left = state.read_u8()?;
+        self.volume_right = state.read_u8()?;
+        self.reg_vin_to_so = state.read_u8()?;
+        self.reg_ff25 = state.read_u8()?;
+        Ok(())
+    }
     
     pub fn step(&mut self, cycles: u32, memory: &mut Memory) {
         self.check_values(memory);
@@ -281,23 +372,23 @@ impl APU {
     
     fn check_values(&mut self, memory: &mut Memory) {
         if !self.enabled {
-            self.channel2.write(0xFF16, *memory.get(0xFF16).unwrap(), self.frame_step);
+            self.channel2.write(0xFF16, memory.get(0xFF16).unwrap(), self.frame_step);
         }
         
         self.run();
         
         for i in 0xFF16..=0xFF19 {
-            self.channel2.write(i, *memory.get(i as usize).unwrap(), self.frame_step)
+            self.channel2.write(i, memory.get(i as usize).unwrap(), self.frame_step)
         }
         
-        let volume = *memory.get(0xFF24).unwrap();
+        let volume = memory.get(0xFF24).unwrap();
         self.volume_left = volume & 0x7;
         self.volume_right = (volume >> 4) & 0x7;
         self.reg_vin_to_so = volume & 0x88;
         
-        self.reg_ff25 = *memory.get(0xFF25).unwrap();
+        self.reg_ff25 = memory.get(0xFF25).unwrap();
         
-        let turn_on = *memory.get(0xFF26).unwrap() & 0x80 == 0x80;
+        let turn_on = memory.get(0xFF26).unwrap() & 0x80 == 0x80;
         if self.enabled && !turn_on {
             for i in 0xFF10..=0xFF25 {
                 memory.write_memory(i, 0);
@@ -0,0 +1,77 @@
+/// What the CPU sees of the rest of the machine: byte reads and writes, plus the
+/// passing of time once an instruction has finished.
+pub trait Bus {
+    fn read(&mut self, address: u16) -> u8;
+    fn write(&mut self, address: u16, value: u8);
+    fn tick(&mut self, cycles: u64);
+}
+
+impl<B: Bus + ?Sized> Bus for &mut B {
+    fn read(&mut self, address: u16) -> u8 {
+        (**self).read(address)
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        (**self).write(address, value)
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        (**self).tick(cycles)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum BusAccess {
+    Read(u16, u8),
+    Write(u16, u8),
+}
+
+/// Forwards everything to an inner bus while logging each access and the elapsed cycles.
+pub struct RecordingBus<B: Bus> {
+    inner: B,
+    accesses: Vec<BusAccess>,
+    cycles: u64,
+}
+
+impl<B: Bus> RecordingBus<B> {
+    pub fn new(inner: B) -> Self {
+        RecordingBus {
+            inner,
+            accesses: Vec::new(),
+            cycles: 0,
+        }
+    }
+
+    pub fn accesses(&self) -> &[BusAccess] {
+        &self.accesses
+    }
+
+    #[cfg(test)]
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    #[cfg(test)]
+    pub fn clear(&mut self) {
+        self.accesses.clear();
+        self.cycles = 0;
+    }
+}
+
+impl<B: Bus> Bus for RecordingBus<B> {
+    fn read(&mut self, address: u16) -> u8 {
+        let value = self.inner.read(address);
+        self.accesses.push(BusAccess::Read(address, value));
+        value
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self.inner.write(address, value);
+        self.accesses.push(BusAccess::Write(address, value));
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        self.inner.tick(cycles);
+        self.cycles += cycles;
+    }
+}
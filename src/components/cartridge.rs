@@ -0,0 +1,580 @@
+mod camera;
+pub mod header;
+mod huc1;
+mod huc3;
+mod mbc1;
+mod mbc2;
+mod mbc3;
+mod mbc5;
+mod mbc6;
+mod mbc7;
+mod mmm01;
+mod no_mbc;
+
+use crate::components::cartridge::camera::PocketCamera;
+pub use crate::components::cartridge::camera::{CameraSource, SENSOR_HEIGHT, SENSOR_WIDTH};
+pub use crate::components::cartridge::header::CartridgeHeader;
+use crate::components::cartridge::huc1::Huc1;
+use crate::components::cartridge::huc3::Huc3;
+use crate::components::cartridge::mbc1::Mbc1;
+use crate::components::cartridge::mbc2::Mbc2;
+use crate::components::cartridge::mbc3::Mbc3;
+use crate::components::cartridge::mbc5::Mbc5;
+use crate::components::cartridge::mbc6::Mbc6;
+use crate::components::cartridge::mbc7::Mbc7;
+use crate::components::cartridge::mmm01::Mmm01;
+pub use crate::components::cartridge::no_mbc::NoMbc;
+use crate::io::savestate::{StateError, StateReader, StateWriter};
+use crate::utils::hardware_identification::cartridge_type_decoder;
+use std::error::Error;
+use std::fmt;
+
+pub const ROM_BANK_SIZE: usize = 0x4000;
+pub const RAM_BANK_SIZE: usize = 0x2000;
+
+/// A cartridge as seen from the bus. It owns its ROM, RAM and banking registers and
+/// answers for 0x0000–0x7FFF (`*_rom`) and 0xA000–0xBFFF (`*_ram`).
+pub trait Cartridge: Send {
+    fn read_rom(&self, address: u16) -> u8;
+    fn write_rom(&mut self, address: u16, value: u8);
+    fn read_ram(&self, address: u16) -> u8;
+    fn write_ram(&mut self, address: u16, value: u8);
+
+    /// Lets mappers with their own clock (MBC3 RTC) follow the emulated time.
+    fn tick(&mut self, _cycles: u64) {}
+
+    /// External RAM as the game sees it, without any banking applied.
+    fn ram(&self) -> &[u8];
+    fn ram_mut(&mut self) -> &mut [u8];
+
+    /// Where `address` (0xA000-0xBFFF) currently lands in `ram()`, `None` if no RAM is
+    /// mapped there.
+    fn mapped_ram_index(&self, address: u16) -> Option<usize> {
+        (!self.ram().is_empty()).then_some(address as usize & 0x1FFF)
+    }
+
+    /// Hands a camera cartridge the pictures its sensor sees.
+    fn set_camera_source(&mut self, _source: CameraSource) {}
+
+    /// Tilts a cartridge with an accelerometer, in g with right and down positive.
+    fn set_tilt(&mut self, _x: f32, _y: f32) {}
+
+    /// Whether the rumble motor is running.
+    fn rumble(&self) -> bool {
+        false
+    }
+
+    /// What the battery keeps alive while the power is off: the RAM, plus the clock on
+    /// mappers that have one.
+    fn save_battery(&self) -> Vec<u8> {
+        self.ram().to_vec()
+    }
+
+    fn load_battery(&mut self, data: &[u8]) {
+        let ram = self.ram_mut();
+        let length = ram.len().min(data.len());
+        ram[..length].copy_from_slice(&data[..length]);
+    }
+
+    /// RAM and banking registers; the ROM itself is never part of a save state.
+    fn save_state(&self, state: &mut StateWriter);
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError>;
+}
+
+#[derive(Debug)]
+pub enum CartridgeError {
+    UnsupportedMapper(u8),
+    /// The ROM ends before the header does.
+    Truncated(usize),
+    InvalidRomSize(u8),
+    RomSizeMismatch {
+        declared: usize,
+        actual: usize,
+    },
+    HeaderChecksumMismatch {
+        stored: u8,
+        computed: u8,
+    },
+}
+
+impl fmt::Display for CartridgeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CartridgeError::UnsupportedMapper(code) => write!(
+                f,
+                "Unsupported cartridge type {:#04X} ({})",
+                code,
+                cartridge_type_decoder(*code)
+            ),
+            CartridgeError::Truncated(length) => write!(
+                f,
+                "ROM is {} bytes, too short to hold a cartridge header",
+                length
+            ),
+            CartridgeError::InvalidRomSize(code) => {
+                write!(f, "Invalid ROM size code {:#04X} in header", code)
+            }
+            CartridgeError::RomSizeMismatch { declared, actual } => write!(
+                f,
+                "Header declares {} bytes of ROM but the file has {}",
+                declared, actual
+            ),
+            CartridgeError::HeaderChecksumMismatch { stored, computed } => write!(
+                f,
+                "Header checksum is {:#04X} but should be {:#04X}",
+                stored, computed
+            ),
+        }
+    }
+}
+
+impl Error for CartridgeError {}
+
+/// Builds the mapper declared at 0x0147 of the ROM header.
+pub fn from_rom(rom: Vec<u8>) -> Result<Box<dyn Cartridge>, CartridgeError> {
+    let header = CartridgeHeader::parse(&rom)?;
+    let code = header.cartridge_type;
+    let ram_size = ram_size(header.ram_size);
+
+    let cartridge: Box<dyn Cartridge> = match code {
+        0x00 => Box::new(NoMbc::new(rom, 0)),
+        0x08 | 0x09 => Box::new(NoMbc::new(rom, ram_size)),
+        0x01..=0x03 => Box::new(Mbc1::new(rom, ram_size)),
+        0x05 | 0x06 => Box::new(Mbc2::new(rom)),
+        0x0B..=0x0D => Box::new(Mmm01::new(rom, ram_size)),
+        0x0F..=0x13 => Box::new(Mbc3::new(rom, ram_size, matches!(code, 0x0F | 0x10))),
+        0x19..=0x1E => Box::new(Mbc5::new(rom, ram_size, code >= 0x1C)),
+        0x20 => Box::new(Mbc6::new(rom, ram_size)),
+        0x22 => Box::new(Mbc7::new(rom)),
+        0xFC => Box::new(PocketCamera::new(rom, ram_size)),
+        0xFE => Box::new(Huc3::new(rom, ram_size)),
+        0xFF => Box::new(Huc1::new(rom, ram_size)),
+        _ => return Err(CartridgeError::UnsupportedMapper(code)),
+    };
+    Ok(cartridge)
+}
+
+/// Whether `from_rom` has a mapper for this cartridge type.
+pub fn is_supported(code: u8) -> bool {
+    matches!(
+        code,
+        0x00 | 0x08 | 0x09 | 0x01..=0x03 | 0x05 | 0x06 | 0x0B..=0x0D | 0x0F..=0x13 | 0x19..=0x1E | 0x20 | 0x22 | 0xFC | 0xFE | 0xFF
+    )
+}
+
+fn ram_size(code: u8) -> usize {
+    match code {
+        1 | 2 => RAM_BANK_SIZE,
+        3 => 4 * RAM_BANK_SIZE,
+        4 => 16 * RAM_BANK_SIZE,
+        5 => 8 * RAM_BANK_SIZE,
+        _ => 0,
+    }
+}
+
+/// Number of 16 KiB banks actually present, so bank numbers can wrap like on hardware.
+fn rom_banks(rom: &[u8]) -> usize {
+    rom.len().div_ceil(ROM_BANK_SIZE).max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// ROM where every bank starts with its own bank number.
+    fn banked_rom(cartridge_type: u8, banks: usize, ram_code: u8) -> Vec<u8> {
+        let mut rom = vec![0; banks * ROM_BANK_SIZE];
+        for bank in 0..banks {
+            rom[bank * ROM_BANK_SIZE] = bank as u8;
+            rom[bank * ROM_BANK_SIZE + 1] = (bank >> 8) as u8;
+        }
+        rom[0x0147] = cartridge_type;
+        rom[0x0149] = ram_code;
+        rom
+    }
+
+    #[test]
+    fn mbc1_switches_banks() {
+        let mut cartridge = from_rom(banked_rom(0x03, 64, 0x03)).unwrap();
+        assert_eq!(cartridge.read_rom(0x4000), 1);
+        cartridge.write_rom(0x2000, 0x00);
+        assert_eq!(cartridge.read_rom(0x4000), 1);
+        cartridge.write_rom(0x2000, 0x05);
+        cartridge.write_rom(0x4000, 0x01);
+        assert_eq!(cartridge.read_rom(0x4000), 0x25);
+        assert_eq!(cartridge.read_rom(0x0000), 0);
+
+        cartridge.write_rom(0x6000, 0x01);
+        assert_eq!(cartridge.read_rom(0x0000), 0x20);
+
+        assert_eq!(cartridge.read_ram(0xA000), 0xFF);
+        cartridge.write_rom(0x0000, 0x0A);
+        cartridge.write_ram(0xA000, 0x42);
+        assert_eq!(cartridge.read_ram(0xA000), 0x42);
+        cartridge.write_rom(0x4000, 0x02);
+        assert_eq!(cartridge.read_ram(0xA000), 0x00);
+    }
+
+    #[test]
+    fn mbc1_multicart_shifts_bank2_by_four() {
+        let mut rom = banked_rom(0x01, 64, 0x00);
+        rom[0x40104..0x40134].copy_from_slice(&header::NINTENDO_LOGO);
+        let mut cartridge = from_rom(rom.clone()).unwrap();
+        cartridge.write_rom(0x2000, 0x12);
+        cartridge.write_rom(0x4000, 0x01);
+        assert_eq!(cartridge.read_rom(0x4000), 0x12);
+        cartridge.write_rom(0x6000, 0x01);
+        assert_eq!(cartridge.read_rom(0x0000), 0x10);
+        cartridge.write_rom(0x2000, 0x10);
+        assert_eq!(cartridge.read_rom(0x4000), 0x10);
+
+        // Without the second logo it is a plain MBC1
+        rom[0x40104] = 0x00;
+        let mut cartridge = from_rom(rom).unwrap();
+        cartridge.write_rom(0x2000, 0x12);
+        cartridge.write_rom(0x4000, 0x01);
+        assert_eq!(cartridge.read_rom(0x4000), 0x32);
+    }
+
+    #[test]
+    fn mbc5_uses_ninth_rom_bank_bit() {
+        let mut cartridge = from_rom(banked_rom(0x19, 512, 0x00)).unwrap();
+        cartridge.write_rom(0x2000, 0x34);
+        cartridge.write_rom(0x3000, 0x01);
+        assert_eq!(cartridge.read_rom(0x4000), 0x34);
+        assert_eq!(cartridge.read_rom(0x4001), 0x01);
+        cartridge.write_rom(0x2000, 0x00);
+        cartridge.write_rom(0x3000, 0x00);
+        assert_eq!(cartridge.read_rom(0x4000), 0x00);
+    }
+
+    #[test]
+    fn mbc5_rumble_bit_is_not_a_ram_bank() {
+        let mut cartridge = from_rom(banked_rom(0x1E, 4, 0x03)).unwrap();
+        cartridge.write_rom(0x0000, 0x0A);
+        cartridge.write_ram(0xA000, 0x42);
+        cartridge.write_rom(0x4000, 0x08);
+        assert!(cartridge.rumble());
+        assert_eq!(cartridge.read_ram(0xA000), 0x42);
+        cartridge.write_rom(0x4000, 0x01);
+        assert!(!cartridge.rumble());
+        assert_eq!(cartridge.read_ram(0xA000), 0x00);
+
+        let mut cartridge = from_rom(banked_rom(0x1B, 4, 0x03)).unwrap();
+        cartridge.write_rom(0x4000, 0x08);
+        assert!(!cartridge.rumble());
+    }
+
+    #[test]
+    fn mbc3_rtc_counts_emulated_time() {
+        let mut cartridge = from_rom(banked_rom(0x10, 4, 0x03)).unwrap();
+        cartridge.write_rom(0x0000, 0x0A);
+        cartridge.write_rom(0x4000, 0x08);
+        cartridge.write_ram(0xA000, 59);
+
+        cartridge.tick(4_194_304);
+        cartridge.write_rom(0x6000, 0x00);
+        cartridge.write_rom(0x6000, 0x01);
+        assert_eq!(cartridge.read_ram(0xA000), 0);
+        cartridge.write_rom(0x4000, 0x09);
+        assert_eq!(cartridge.read_ram(0xA000), 1);
+    }
+
+    #[test]
+    fn battery_keeps_ram_and_clock() {
+        let mut cartridge = from_rom(banked_rom(0x10, 4, 0x03)).unwrap();
+        cartridge.write_rom(0x0000, 0x0A);
+        cartridge.write_ram(0xA000, 0x42);
+        cartridge.write_rom(0x4000, 0x0A);
+        cartridge.write_ram(0xA000, 5);
+        let battery = cartridge.save_battery();
+        assert_eq!(battery.len(), 0x8000 + 48);
+
+        let mut restored = from_rom(banked_rom(0x10, 4, 0x03)).unwrap();
+        restored.load_battery(&battery);
+        restored.write_rom(0x0000, 0x0A);
+        assert_eq!(restored.read_ram(0xA000), 0x42);
+        restored.write_rom(0x6000, 0x00);
+        restored.write_rom(0x6000, 0x01);
+        restored.write_rom(0x4000, 0x0A);
+        assert_eq!(restored.read_ram(0xA000), 5);
+    }
+
+    #[test]
+    fn huc1_maps_ir_register_over_ram() {
+        let mut cartridge = from_rom(banked_rom(0xFF, 64, 0x03)).unwrap();
+        cartridge.write_rom(0x2000, 0x3F);
+        assert_eq!(cartridge.read_rom(0x4000), 0x3F);
+        cartridge.write_ram(0xA000, 0x42);
+        cartridge.write_rom(0x0000, 0x0E);
+        assert_eq!(cartridge.read_ram(0xA000), 0xC0);
+        cartridge.write_ram(0xA000, 0x01);
+        cartridge.write_rom(0x0000, 0x00);
+        assert_eq!(cartridge.read_ram(0xA000), 0x42);
+    }
+
+    /// Sends a HuC3 clock command and returns the response.
+    fn huc3_command(cartridge: &mut Box<dyn Cartridge>, command: u8) -> u8 {
+        cartridge.write_rom(0x0000, 0x0B);
+        cartridge.write_ram(0xA000, command);
+        cartridge.write_rom(0x0000, 0x0C);
+        cartridge.read_ram(0xA000)
+    }
+
+    #[test]
+    fn huc3_clock_is_set_read_and_saved() {
+        let mut cartridge = from_rom(banked_rom(0xFE, 4, 0x03)).unwrap();
+        // 23:59 on day 2, written nibble by nibble to 0x00-0x05
+        huc3_command(&mut cartridge, 0x40);
+        huc3_command(&mut cartridge, 0x50);
+        for nibble in [0xF, 0x9, 0x5, 0x2, 0x0, 0x0] {
+            huc3_command(&mut cartridge, 0x30 | nibble);
+        }
+        huc3_command(&mut cartridge, 0x61);
+        cartridge.tick(4_194_304 * 60);
+
+        huc3_command(&mut cartridge, 0x60);
+        huc3_command(&mut cartridge, 0x40);
+        let time: Vec<u8> = (0..6)
+            .map(|_| huc3_command(&mut cartridge, 0x10) & 0x0F)
+            .collect();
+        assert_eq!(time, [0, 0, 0, 3, 0, 0]);
+
+        cartridge.write_rom(0x0000, 0x0A);
+        cartridge.write_ram(0xA000, 0x42);
+        let battery = cartridge.save_battery();
+        assert_eq!(battery.len(), 0x8000 + 16);
+        let mut restored = from_rom(banked_rom(0xFE, 4, 0x03)).unwrap();
+        restored.load_battery(&battery);
+        assert_eq!(restored.read_ram(0xA000), 0x42);
+        huc3_command(&mut restored, 0x60);
+        huc3_command(&mut restored, 0x43);
+        assert_eq!(huc3_command(&mut restored, 0x10), 0x93);
+    }
+
+    #[test]
+    fn mmm01_boots_the_menu_then_locks_the_game() {
+        let mut rom = banked_rom(0x00, 16, 0x00);
+        let menu = &mut rom[14 * ROM_BANK_SIZE..];
+        menu[0x0104..0x0134].copy_from_slice(&header::NINTENDO_LOGO);
+        menu[0x0147] = 0x0B;
+        menu[0x014D] = header::header_checksum(menu);
+        let header = CartridgeHeader::parse(&rom).unwrap();
+        assert_eq!(header.cartridge_type, 0x0B);
+
+        let mut cartridge = from_rom(rom).unwrap();
+        assert_eq!(cartridge.read_rom(0x0000), 14);
+        assert_eq!(cartridge.read_rom(0x4000), 15);
+
+        // A four bank game starting at bank 4
+        cartridge.write_rom(0x2000, 0x04);
+        cartridge.write_rom(0x6000, 0x38);
+        cartridge.write_rom(0x0000, 0x40);
+        assert_eq!(cartridge.read_rom(0x0000), 4);
+        assert_eq!(cartridge.read_rom(0x4000), 4);
+        cartridge.write_rom(0x2000, 0x03);
+        assert_eq!(cartridge.read_rom(0x4000), 7);
+        cartridge.write_rom(0x2000, 0x1D);
+        assert_eq!(cartridge.read_rom(0x4000), 5);
+
+        // The menu registers are locked now
+        cartridge.write_rom(0x6000, 0x00);
+        cartridge.write_rom(0x2000, 0x00);
+        assert_eq!(cartridge.read_rom(0x4000), 5);
+        cartridge.write_rom(0x0000, 0x00);
+        assert_eq!(cartridge.read_rom(0x0000), 4);
+    }
+
+    #[test]
+    fn mmm01_byte_in_an_ordinary_rom_is_not_a_menu() {
+        let mut rom = banked_rom(0x01, 16, 0x00);
+        rom[14 * ROM_BANK_SIZE + 0x0147] = 0x0B;
+        let header = CartridgeHeader::parse(&rom).unwrap();
+        assert_eq!(header.cartridge_type, 0x01);
+
+        let cartridge = from_rom(rom).unwrap();
+        assert_eq!(cartridge.read_rom(0x0000), 0);
+        assert_eq!(cartridge.read_rom(0x4000), 1);
+    }
+
+    /// Writes `value` to the flash through window A, unlocking it first.
+    fn mbc6_flash_command(
+        cartridge: &mut Box<dyn Cartridge>,
+        command: u8,
+        address: u16,
+        value: u8,
+    ) {
+        for (bank, offset, byte) in [(2, 0x5555, 0xAA), (1, 0x4AAA, 0x55), (2, 0x5555, command)] {
+            cartridge.write_rom(0x2000, bank);
+            cartridge.write_rom(offset, byte);
+        }
+        cartridge.write_rom(0x2000, (address >> 13) as u8);
+        cartridge.write_rom(0x4000 | (address & 0x1FFF), value);
+    }
+
+    #[test]
+    fn mbc6_windows_map_rom_and_flash() {
+        let mut cartridge = from_rom(banked_rom(0x20, 8, 0x03)).unwrap();
+        cartridge.write_rom(0x2000, 4);
+        cartridge.write_rom(0x3000, 6);
+        assert_eq!(cartridge.read_rom(0x4000), 2);
+        assert_eq!(cartridge.read_rom(0x6000), 3);
+
+        cartridge.write_rom(0x0000, 0x0A);
+        cartridge.write_rom(0x0400, 0x01);
+        cartridge.write_ram(0xA000, 0x42);
+        cartridge.write_rom(0x0800, 0x01);
+        assert_eq!(cartridge.read_ram(0xB000), 0x42);
+
+        cartridge.write_rom(0x0C00, 0x01);
+        cartridge.write_rom(0x1000, 0x01);
+        cartridge.write_rom(0x2800, 0x08);
+        mbc6_flash_command(&mut cartridge, 0xA0, 0x6123, 0x5A);
+        mbc6_flash_command(&mut cartridge, 0xA0, 0x6123, 0xF0);
+        cartridge.write_rom(0x2000, 0x03);
+        assert_eq!(cartridge.read_rom(0x4123), 0x50);
+        assert_eq!(cartridge.read_rom(0x6000), 3);
+
+        let battery = cartridge.save_battery();
+        let mut restored = from_rom(banked_rom(0x20, 8, 0x03)).unwrap();
+        restored.load_battery(&battery);
+        restored.write_rom(0x0C00, 0x01);
+        restored.write_rom(0x2800, 0x08);
+        restored.write_rom(0x2000, 0x03);
+        assert_eq!(restored.read_rom(0x4123), 0x50);
+
+        mbc6_flash_command(&mut cartridge, 0x80, 0x5555, 0xAA);
+        cartridge.write_rom(0x2000, 1);
+        cartridge.write_rom(0x4AAA, 0x55);
+        cartridge.write_rom(0x2000, 3);
+        cartridge.write_rom(0x4000, 0x30);
+        cartridge.write_rom(0x2000, 0x03);
+        assert_eq!(cartridge.read_rom(0x4123), 0xFF);
+    }
+
+    #[test]
+    fn header_checksums_are_verified() {
+        let mut rom = banked_rom(0x01, 4, 0x00);
+        rom[0x0134..0x0139].copy_from_slice(b"TETRA");
+        rom[0x0148] = 0x01;
+        rom[0x014D] = header::header_checksum(&rom);
+        let header = CartridgeHeader::parse(&rom).unwrap();
+        assert_eq!(header.title, "TETRA");
+        assert!(header.validate(&rom).is_ok());
+        assert!(!header.global_checksum_valid());
+
+        let [high, low] = header::global_checksum(&rom).to_be_bytes();
+        rom[0x014E] = high;
+        rom[0x014F] = low;
+        assert!(
+            CartridgeHeader::parse(&rom)
+                .unwrap()
+                .global_checksum_valid()
+        );
+
+        rom[0x014D] ^= 0xFF;
+        let header = CartridgeHeader::parse(&rom).unwrap();
+        assert!(matches!(
+            header.validate(&rom),
+            Err(CartridgeError::HeaderChecksumMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn inconsistent_roms_are_rejected() {
+        assert!(matches!(
+            CartridgeHeader::parse(&[0; 0x100]),
+            Err(CartridgeError::Truncated(0x100))
+        ));
+
+        let mut rom = banked_rom(0x01, 4, 0x00);
+        rom[0x0148] = 0x02;
+        rom[0x014D] = header::header_checksum(&rom);
+        assert!(matches!(
+            CartridgeHeader::parse(&rom).unwrap().validate(&rom),
+            Err(CartridgeError::RomSizeMismatch {
+                declared: 0x20000,
+                actual: 0x10000
+            })
+        ));
+    }
+
+    #[test]
+    fn pocket_camera_captures_through_the_matrix() {
+        let mut cartridge = from_rom(banked_rom(0xFC, 4, 0x04)).unwrap();
+        let mut frame = vec![0xFF; SENSOR_WIDTH * SENSOR_HEIGHT];
+        frame[..SENSOR_WIDTH * 8].fill(0x00);
+        cartridge.set_camera_source(CameraSource::new(vec![frame]));
+
+        cartridge.write_rom(0x4000, 0x10);
+        cartridge.write_ram(0xA002, 0x10);
+        cartridge.write_ram(0xA003, 0x00);
+        for pixel in 0..16 {
+            for (index, threshold) in [0x40, 0x80, 0xC0].into_iter().enumerate() {
+                cartridge.write_ram(0xA006 + pixel * 3 + index as u16, threshold);
+            }
+        }
+        cartridge.write_ram(0xA000, 0x01);
+        assert_eq!(cartridge.read_ram(0xA000), 0x01);
+        cartridge.tick((32446 + 16 * 0x1000) * 4);
+        assert_eq!(cartridge.read_ram(0xA000), 0x00);
+
+        // The dark top row of tiles is black, the rest white
+        cartridge.write_rom(0x4000, 0x00);
+        assert_eq!(cartridge.read_ram(0xA100), 0xFF);
+        assert_eq!(cartridge.read_ram(0xA101), 0xFF);
+        assert_eq!(cartridge.read_ram(0xA100 + 16 * 16), 0x00);
+        assert_eq!(cartridge.read_ram(0xA101 + 16 * 16), 0x00);
+    }
+
+    /// Bit-bangs `bits` into the MBC7 EEPROM, most significant first, and returns what
+    /// DO read after each rising clock edge.
+    fn clock_eeprom(cartridge: &mut Box<dyn Cartridge>, value: u32, bits: u32) -> u32 {
+        let mut output = 0;
+        for bit in (0..bits).rev() {
+            let di = ((value >> bit) & 1) as u8 * 0x02;
+            cartridge.write_ram(0xA080, 0x80 | di);
+            cartridge.write_ram(0xA080, 0xC0 | di);
+            output = (output << 1) | (cartridge.read_ram(0xA080) & 0x01) as u32;
+        }
+        output
+    }
+
+    #[test]
+    fn mbc7_eeprom_and_accelerometer() {
+        let mut cartridge = from_rom(banked_rom(0x22, 8, 0x00)).unwrap();
+        cartridge.write_rom(0x0000, 0x0A);
+        cartridge.write_rom(0x4000, 0x40);
+
+        // Commands are a start bit, 2 opcode bits and 8 address bits.
+        // EWEN, then WRITE 0x1234 to word 5
+        clock_eeprom(&mut cartridge, 0x4C0, 11);
+        cartridge.write_ram(0xA080, 0x00);
+        clock_eeprom(&mut cartridge, (0x505 << 16) | 0x1234, 27);
+        cartridge.write_ram(0xA080, 0x00);
+        // READ word 5: a dummy 0, then 16 bits
+        clock_eeprom(&mut cartridge, 0x605, 11);
+        assert_eq!(clock_eeprom(&mut cartridge, 0, 16), 0x1234);
+        cartridge.write_ram(0xA080, 0x00);
+        assert_eq!(&cartridge.save_battery()[10..12], &[0x34, 0x12]);
+
+        cartridge.set_tilt(1.0, -0.5);
+        cartridge.write_ram(0xA010, 0xAA);
+        assert_eq!(cartridge.read_ram(0xA020), 0x00);
+        assert_eq!(cartridge.read_ram(0xA030), 0x80);
+        cartridge.write_ram(0xA000, 0x55);
+        cartridge.write_ram(0xA010, 0xAA);
+        let x = u16::from_le_bytes([cartridge.read_ram(0xA020), cartridge.read_ram(0xA030)]);
+        let y = u16::from_le_bytes([cartridge.read_ram(0xA040), cartridge.read_ram(0xA050)]);
+        assert_eq!((x, y), (0x81D0 + 0x70, 0x81D0 - 0x38));
+    }
+
+    #[test]
+    fn unsupported_mapper_is_an_error() {
+        assert!(matches!(
+            from_rom(banked_rom(0xFD, 2, 0x00)),
+            Err(CartridgeError::UnsupportedMapper(0xFD))
+        ));
+        assert!(!is_supported(0xFD));
+    }
+}
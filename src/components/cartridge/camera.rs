@@ -0,0 +1,254 @@
+use crate::components::cartridge::{Cartridge, RAM_BANK_SIZE, ROM_BANK_SIZE, rom_banks};
+use crate::io::savestate::{StateError, StateReader, StateWriter};
+use std::sync::Arc;
+
+pub const SENSOR_WIDTH: usize = 128;
+/// The sensor has 128 rows but only 112 of them end up in the picture.
+pub const SENSOR_HEIGHT: usize = 112;
+
+const REGISTER_COUNT: usize = 0x36;
+/// Where the picture lands in RAM bank 0, as 16x14 tiles.
+const PICTURE_OFFSET: usize = 0x0100;
+/// Exposure time that passes the image through unchanged.
+const NEUTRAL_EXPOSURE: i32 = 0x1000;
+/// Edge enhancement ratios from register 4, in quarters.
+const EDGE_RATIOS: [i32; 8] = [2, 3, 4, 5, 8, 12, 16, 20];
+
+/// Grayscale pictures, `SENSOR_WIDTH` by `SENSOR_HEIGHT`, that the sensor sees in turn:
+/// each capture takes the next one. Cheap to clone.
+#[derive(Clone)]
+pub struct CameraSource {
+    frames: Arc<[Vec<u8>]>,
+}
+
+impl CameraSource {
+    pub fn new(frames: Vec<Vec<u8>>) -> Self {
+        assert!(
+            !frames.is_empty(),
+            "A camera source needs at least one picture"
+        );
+        CameraSource {
+            frames: frames.into(),
+        }
+    }
+}
+
+impl Default for CameraSource {
+    /// A diagonal gradient, so captures show something without a picture being loaded.
+    fn default() -> Self {
+        let frame = (0..SENSOR_HEIGHT)
+            .flat_map(|y| {
+                (0..SENSOR_WIDTH)
+                    .map(move |x| ((x + y) * 255 / (SENSOR_WIDTH + SENSOR_HEIGHT)) as u8)
+            })
+            .collect();
+        CameraSource::new(vec![frame])
+    }
+}
+
+/// The Pocket Camera's mapper and its M64282FP image sensor. Writing 0x10 to the RAM bank
+/// register maps the sensor registers to 0xA000 instead of RAM:
+///
+/// - A000: bit 0 starts a capture and reads 1 until it is done
+/// - A001: bits 5-6 select edge enhancement (none, horizontal, vertical, both)
+/// - A002-A003: exposure time, big endian
+/// - A004: bits 4-6 edge enhancement ratio, bit 7 inverts the picture
+/// - A006-A035: 4x4 dithering matrix, three thresholds per pixel
+pub struct PocketCamera {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    rom_banks: usize,
+    ram_enabled: bool,
+    rom_bank: usize,
+    ram_bank: usize,
+    registers_selected: bool,
+    registers: [u8; REGISTER_COUNT],
+    /// Cycles until the running capture finishes, 0 when idle.
+    capture_cycles: u64,
+    source: CameraSource,
+    /// Which picture of the source the next capture takes.
+    frame: usize,
+}
+
+impl PocketCamera {
+    pub fn new(rom: Vec<u8>, ram_size: usize) -> Self {
+        PocketCamera {
+            rom_banks: rom_banks(&rom),
+            rom,
+            ram: vec![0; ram_size.max(RAM_BANK_SIZE)],
+            ram_enabled: false,
+            rom_bank: 1,
+            ram_bank: 0,
+            registers_selected: false,
+            registers: [0; REGISTER_COUNT],
+            capture_cycles: 0,
+            source: CameraSource::default(),
+            frame: 0,
+        }
+    }
+
+    fn ram_index(&self, address: u16) -> usize {
+        (self.ram_bank * RAM_BANK_SIZE + (address as usize & 0x1FFF)) % self.ram.len()
+    }
+
+    fn exposure(&self) -> u16 {
+        u16::from_be_bytes([self.registers[2], self.registers[3]])
+    }
+
+    /// Runs the picture through exposure, edge enhancement and the dithering matrix and
+    /// stores it as tiles.
+    fn capture(&mut self) {
+        let frame = &self.source.frames[self.frame % self.source.frames.len()];
+        self.frame = (self.frame + 1) % self.source.frames.len();
+
+        let exposure = self.exposure() as i32;
+        let exposed = |x: usize, y: usize| {
+            let x = x.min(SENSOR_WIDTH - 1);
+            let y = y.min(SENSOR_HEIGHT - 1);
+            (frame[y * SENSOR_WIDTH + x] as i32 * exposure / NEUTRAL_EXPOSURE).min(255)
+        };
+        let edge_mode = (self.registers[1] >> 5) & 0x03;
+        let ratio = EDGE_RATIOS[((self.registers[4] >> 4) & 0x07) as usize];
+        let invert = self.registers[4] & 0x80 != 0;
+
+        let picture =
+            &mut self.ram[PICTURE_OFFSET..PICTURE_OFFSET + SENSOR_WIDTH * SENSOR_HEIGHT / 4];
+        picture.fill(0);
+        for y in 0..SENSOR_HEIGHT {
+            for x in 0..SENSOR_WIDTH {
+                let value = exposed(x, y);
+                let horizontal = 2 * value - exposed(x.saturating_sub(1), y) - exposed(x + 1, y);
+                let vertical = 2 * value - exposed(x, y.saturating_sub(1)) - exposed(x, y + 1);
+                let edge = match edge_mode {
+                    0 => 0,
+                    1 => horizontal,
+                    2 => vertical,
+                    _ => horizontal + vertical,
+                };
+                let mut value = (value + edge * ratio / 4).clamp(0, 255) as u8;
+                if invert {
+                    value = 255 - value;
+                }
+
+                let matrix = 6 + ((y % 4) * 4 + x % 4) * 3;
+                let thresholds = &self.registers[matrix..matrix + 3];
+                let color = 3 - thresholds
+                    .iter()
+                    .filter(|&&threshold| value >= threshold)
+                    .count() as u8;
+
+                let offset = ((y / 8) * 16 + x / 8) * 16 + (y % 8) * 2;
+                let bit = 7 - (x % 8);
+                picture[offset] |= (color & 0x01) << bit;
+                picture[offset + 1] |= (color >> 1) << bit;
+            }
+        }
+    }
+}
+
+impl Cartridge for PocketCamera {
+    fn read_rom(&self, address: u16) -> u8 {
+        let bank = if address < 0x4000 {
+            0
+        } else {
+            self.rom_bank % self.rom_banks
+        };
+        let index = bank * ROM_BANK_SIZE + (address as usize & 0x3FFF);
+        self.rom.get(index).copied().unwrap_or(0xFF)
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..0x2000 => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..0x4000 => self.rom_bank = (value & 0x3F) as usize,
+            0x4000..0x6000 => {
+                self.registers_selected = value & 0x10 != 0;
+                self.ram_bank = (value & 0x0F) as usize;
+            }
+            _ => {}
+        }
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        if self.registers_selected {
+            // Only the capture register can be read back
+            return if address & 0x7F == 0 {
+                self.registers[0] & 0x07
+            } else {
+                0x00
+            };
+        }
+        self.ram[self.ram_index(address)]
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if self.registers_selected {
+            let register = (address & 0x7F) as usize;
+            if register == 0 {
+                self.registers[0] = value & 0x07;
+                if value & 0x01 != 0 && self.capture_cycles == 0 {
+                    // 32446 M-cycles plus 16 per unit of exposure
+                    self.capture_cycles = (32446 + 16 * self.exposure() as u64) * 4;
+                }
+            } else if register < REGISTER_COUNT {
+                self.registers[register] = value;
+            }
+            return;
+        }
+        if self.ram_enabled {
+            let index = self.ram_index(address);
+            self.ram[index] = value;
+        }
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        if self.capture_cycles == 0 {
+            return;
+        }
+        self.capture_cycles = self.capture_cycles.saturating_sub(cycles);
+        if self.capture_cycles == 0 {
+            self.capture();
+            self.registers[0] &= !0x01;
+        }
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
+    fn mapped_ram_index(&self, address: u16) -> Option<usize> {
+        (!self.registers_selected).then(|| self.ram_index(address))
+    }
+
+    fn set_camera_source(&mut self, source: CameraSource) {
+        self.source = source;
+        self.frame = 0;
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.ram);
+        state.write_bool(self.ram_enabled);
+        state.write_u8(self.rom_bank as u8);
+        state.write_u8(self.ram_bank as u8);
+        state.write_bool(self.registers_selected);
+        state.write_bytes(&self.registers);
+        state.write_u64(self.capture_cycles);
+        state.write_u32(self.frame as u32);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        state.read_bytes_into(&mut self.ram)?;
+        self.ram_enabled = state.read_bool()?;
+        self.rom_bank = state.read_u8()? as usize;
+        self.ram_bank = state.read_u8()? as usize;
+        self.registers_selected = state.read_bool()?;
+        state.read_bytes_into(&mut self.registers)?;
+        self.capture_cycles = state.read_u64()?;
+        self.frame = state.read_u32()? as usize;
+        Ok(())
+    }
+}
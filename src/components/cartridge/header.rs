@@ -0,0 +1,235 @@
+use crate::components::cartridge::{CartridgeError, ROM_BANK_SIZE};
+use crate::utils::hardware_identification::{
+    cartridge_type_decoder, destination_decoder, ram_size_decoder, rom_size_decoder,
+};
+use crate::utils::licensee::{new_licensee_code_decryption, old_licensee_code_decryption};
+use std::fmt;
+
+/// The header occupies 0x0100–0x014F, so anything shorter cannot be a cartridge.
+pub const HEADER_END: usize = 0x0150;
+
+/// The boot ROM refuses to start a cartridge without this at 0x0104.
+pub const NINTENDO_LOGO: [u8; 48] = [
+    0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0C, 0x00, 0x0D,
+    0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E, 0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99,
+    0xBB, 0xBB, 0x67, 0x63, 0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E,
+];
+
+/// Cartridge header fields at 0x0134–0x014F, as stored in the ROM.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CartridgeHeader {
+    pub title: String,
+    pub manufacturer_code: String,
+    pub cgb_flag: u8,
+    pub sgb_flag: u8,
+    pub cartridge_type: u8,
+    pub rom_size: u8,
+    pub ram_size: u8,
+    pub destination_code: u8,
+    pub old_licensee_code: u8,
+    pub new_licensee_code: String,
+    pub licensee: String,
+    pub version: u8,
+    pub header_checksum: u8,
+    pub global_checksum: u16,
+    pub computed_header_checksum: u8,
+    pub computed_global_checksum: u16,
+}
+
+impl CartridgeHeader {
+    pub fn parse(full_rom: &[u8]) -> Result<Self, CartridgeError> {
+        if full_rom.len() < HEADER_END {
+            return Err(CartridgeError::Truncated(full_rom.len()));
+        }
+        let rom = header_bank(full_rom);
+
+        let cgb_flag = rom[0x0143];
+        // On CGB-era carts the last title bytes hold the manufacturer code and CGB flag.
+        let title_end = if cgb_flag & 0x80 != 0 { 0x013F } else { 0x0144 };
+        let title = ascii_field(&rom[0x0134..title_end]);
+        let manufacturer_code = if cgb_flag & 0x80 != 0 {
+            ascii_field(&rom[0x013F..0x0143])
+        } else {
+            String::new()
+        };
+
+        let old_licensee_code = rom[0x014B];
+        let new_licensee_code = ascii_field(&rom[0x0144..0x0146]);
+        let licensee = if old_licensee_code == 0x33 {
+            new_licensee_code_decryption(new_licensee_code.clone())
+        } else {
+            old_licensee_code_decryption(old_licensee_code)
+        };
+
+        Ok(CartridgeHeader {
+            title,
+            manufacturer_code,
+            cgb_flag,
+            sgb_flag: rom[0x0146],
+            cartridge_type: rom[0x0147],
+            rom_size: rom[0x0148],
+            ram_size: rom[0x0149],
+            destination_code: rom[0x014A],
+            old_licensee_code,
+            new_licensee_code,
+            licensee,
+            version: rom[0x014C],
+            header_checksum: rom[0x014D],
+            global_checksum: u16::from_be_bytes([rom[0x014E], rom[0x014F]]),
+            computed_header_checksum: header_checksum(rom),
+            computed_global_checksum: global_checksum(full_rom),
+        })
+    }
+
+    /// Checks the header against the whole ROM it came from. The global checksum is
+    /// not checked by hardware, so a mismatch there is left to the caller.
+    pub fn validate(&self, rom: &[u8]) -> Result<(), CartridgeError> {
+        if !self.header_checksum_valid() {
+            return Err(CartridgeError::HeaderChecksumMismatch {
+                stored: self.header_checksum,
+                computed: self.computed_header_checksum,
+            });
+        }
+        let declared = self
+            .declared_rom_size()
+            .ok_or(CartridgeError::InvalidRomSize(self.rom_size))?;
+        if rom.len() != declared {
+            return Err(CartridgeError::RomSizeMismatch {
+                declared,
+                actual: rom.len(),
+            });
+        }
+        Ok(())
+    }
+
+    pub fn header_checksum_valid(&self) -> bool {
+        self.header_checksum == self.computed_header_checksum
+    }
+
+    pub fn global_checksum_valid(&self) -> bool {
+        self.global_checksum == self.computed_global_checksum
+    }
+
+    pub fn declared_rom_size(&self) -> Option<usize> {
+        match self.rom_size {
+            0x00..=0x08 => Some(0x8000 << self.rom_size),
+            0x52 => Some(72 * ROM_BANK_SIZE),
+            0x53 => Some(80 * ROM_BANK_SIZE),
+            0x54 => Some(96 * ROM_BANK_SIZE),
+            _ => None,
+        }
+    }
+
+    pub fn supports_cgb(&self) -> bool {
+        self.cgb_flag & 0x80 != 0
+    }
+
+    pub fn cgb_only(&self) -> bool {
+        self.cgb_flag == 0xC0
+    }
+
+    pub fn cgb_support(&self) -> &'static str {
+        if self.cgb_only() {
+            "required"
+        } else if self.supports_cgb() {
+            "supported"
+        } else {
+            "no"
+        }
+    }
+
+    pub fn supports_sgb(&self) -> bool {
+        self.sgb_flag == 0x03
+    }
+
+    /// Whether the cartridge RAM (and clock, if any) survives the power being cut.
+    pub fn has_battery(&self) -> bool {
+        matches!(
+            self.cartridge_type,
+            0x03 | 0x06
+                | 0x09
+                | 0x0D
+                | 0x0F
+                | 0x10
+                | 0x13
+                | 0x1B
+                | 0x1E
+                | 0x20
+                | 0x22
+                | 0xFC
+                | 0xFE
+                | 0xFF
+        )
+    }
+}
+
+impl fmt::Display for CartridgeHeader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Game Title: {}", self.title)?;
+        writeln!(f, "Manufacturer Code: {}", self.manufacturer_code)?;
+        writeln!(f, "CGB: {}", self.cgb_support())?;
+        writeln!(f, "SGB: {}", if self.supports_sgb() { "yes" } else { "no" })?;
+        writeln!(
+            f,
+            "Hardware present: {}",
+            cartridge_type_decoder(self.cartridge_type)
+        )?;
+        writeln!(f, "Rom size: {}", rom_size_decoder(self.rom_size))?;
+        writeln!(f, "Ram size: {}", ram_size_decoder(self.ram_size))?;
+        writeln!(
+            f,
+            "Destination: {}",
+            destination_decoder(self.destination_code)
+        )?;
+        writeln!(f, "Licensee: {}", self.licensee)?;
+        writeln!(f, "Version number: {}", self.version)?;
+        writeln!(
+            f,
+            "Header checksum: {:#04X} (computed {:#04X})",
+            self.header_checksum, self.computed_header_checksum
+        )?;
+        write!(
+            f,
+            "Global checksum: {:#06X} (computed {:#06X})",
+            self.global_checksum, self.computed_global_checksum
+        )
+    }
+}
+
+/// MMM01 compilations boot into a menu in the last 32 KiB, and the header there is the
+/// one that describes the cartridge; bank 0 holds the first game's. Anything else may
+/// have arbitrary data there, so the menu header only counts if it is a real one.
+fn header_bank(rom: &[u8]) -> &[u8] {
+    let menu = &rom[rom.len().saturating_sub(0x8000)..];
+    let is_mmm01_menu = menu.len() >= HEADER_END
+        && matches!(menu[0x0147], 0x0B..=0x0D)
+        && menu[0x0104..0x0134] == NINTENDO_LOGO
+        && menu[0x014D] == header_checksum(menu);
+    if is_mmm01_menu { menu } else { rom }
+}
+
+/// Checksum over 0x0134–0x014C, verified by the boot ROM.
+pub fn header_checksum(rom: &[u8]) -> u8 {
+    rom[0x0134..=0x014C].iter().fold(0u8, |checksum, &byte| {
+        checksum.wrapping_sub(byte).wrapping_sub(1)
+    })
+}
+
+/// Sum of every ROM byte except the two global checksum bytes themselves.
+pub fn global_checksum(rom: &[u8]) -> u16 {
+    rom.iter()
+        .enumerate()
+        .filter(|(address, _)| !matches!(address, 0x014E | 0x014F))
+        .fold(0u16, |sum, (_, &byte)| sum.wrapping_add(byte as u16))
+}
+
+fn ascii_field(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .take_while(|&&byte| byte != 0)
+        .filter(|byte| byte.is_ascii_graphic() || **byte == b' ')
+        .map(|&byte| byte as char)
+        .collect::<String>()
+        .trim_end()
+        .to_string()
+}
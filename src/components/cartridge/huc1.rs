@@ -0,0 +1,107 @@
+use crate::components::cartridge::{Cartridge, RAM_BANK_SIZE, ROM_BANK_SIZE, rom_banks};
+use crate::io::savestate::{StateError, StateReader, StateWriter};
+
+/// What the IR register reads when the sensor sees no light.
+const IR_DARK: u8 = 0xC0;
+
+/// Hudson's HuC1: MBC1-style banking plus an infrared LED and sensor. Writing 0x0E to
+/// 0000-1FFF maps the IR register to A000-BFFF in place of RAM; bit 0 drives the LED
+/// and reads back whether the sensor sees light.
+pub struct Huc1 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    rom_banks: usize,
+    rom_bank: usize,
+    ram_bank: usize,
+    ir_selected: bool,
+    led_on: bool,
+}
+
+impl Huc1 {
+    pub fn new(rom: Vec<u8>, ram_size: usize) -> Self {
+        Huc1 {
+            rom_banks: rom_banks(&rom),
+            rom,
+            ram: vec![0; ram_size],
+            rom_bank: 1,
+            ram_bank: 0,
+            ir_selected: false,
+            led_on: false,
+        }
+    }
+
+    fn ram_index(&self, address: u16) -> usize {
+        (self.ram_bank * RAM_BANK_SIZE + (address as usize & 0x1FFF)) % self.ram.len()
+    }
+}
+
+impl Cartridge for Huc1 {
+    fn read_rom(&self, address: u16) -> u8 {
+        let bank = if address < 0x4000 {
+            0
+        } else {
+            self.rom_bank % self.rom_banks
+        };
+        let index = bank * ROM_BANK_SIZE + (address as usize & 0x3FFF);
+        self.rom.get(index).copied().unwrap_or(0xFF)
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..0x2000 => self.ir_selected = value & 0x0F == 0x0E,
+            0x2000..0x4000 => self.rom_bank = (value & 0x3F) as usize,
+            0x4000..0x6000 => self.ram_bank = (value & 0x03) as usize,
+            _ => {}
+        }
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        if self.ir_selected {
+            // Nothing is on the other end, so the sensor never sees light
+            IR_DARK
+        } else if self.ram.is_empty() {
+            0xFF
+        } else {
+            self.ram[self.ram_index(address)]
+        }
+    }
+
+    /// There is no RAM enable; RAM is writable whenever the IR register isn't mapped.
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if self.ir_selected {
+            self.led_on = value & 0x01 != 0;
+        } else if !self.ram.is_empty() {
+            let index = self.ram_index(address);
+            self.ram[index] = value;
+        }
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
+    fn mapped_ram_index(&self, address: u16) -> Option<usize> {
+        (!self.ir_selected && !self.ram.is_empty()).then(|| self.ram_index(address))
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.ram);
+        state.write_u8(self.rom_bank as u8);
+        state.write_u8(self.ram_bank as u8);
+        state.write_bool(self.ir_selected);
+        state.write_bool(self.led_on);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        state.read_bytes_into(&mut self.ram)?;
+        self.rom_bank = state.read_u8()? as usize;
+        self.ram_bank = state.read_u8()? as usize;
+        self.ir_selected = state.read_bool()?;
+        self.led_on = state.read_bool()?;
+        Ok(())
+    }
+}
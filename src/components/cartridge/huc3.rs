@@ -0,0 +1,268 @@
+use crate::components::cartridge::{Cartridge, RAM_BANK_SIZE, ROM_BANK_SIZE, rom_banks};
+use crate::io::savestate::{StateError, StateReader, StateWriter};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const CYCLES_PER_MINUTE: u64 = 4_194_304 * 60;
+const MINUTES_PER_DAY: u16 = 24 * 60;
+/// What the IR register reads when the sensor sees no light.
+const IR_DARK: u8 = 0xC0;
+
+/// What A000-BFFF is mapped to, selected by writing to 0000-1FFF.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Mode {
+    RamReadOnly,
+    Ram,
+    /// Writes send a command to the clock chip.
+    Command,
+    /// Reads return the last command and its result.
+    Response,
+    /// Reads whether the clock chip is ready for the next command.
+    Semaphore,
+    Ir,
+    Unmapped,
+}
+
+impl Mode {
+    fn from_value(value: u8) -> Mode {
+        match value & 0x0F {
+            0x00 => Mode::RamReadOnly,
+            0x0A => Mode::Ram,
+            0x0B => Mode::Command,
+            0x0C => Mode::Response,
+            0x0D => Mode::Semaphore,
+            0x0E => Mode::Ir,
+            _ => Mode::Unmapped,
+        }
+    }
+
+    fn to_value(self) -> u8 {
+        match self {
+            Mode::RamReadOnly => 0x00,
+            Mode::Ram => 0x0A,
+            Mode::Command => 0x0B,
+            Mode::Response => 0x0C,
+            Mode::Semaphore => 0x0D,
+            Mode::Ir => 0x0E,
+            Mode::Unmapped => 0x0F,
+        }
+    }
+}
+
+/// Hudson's HuC3, with a clock chip and an IR port. The clock is driven through a
+/// command register: the high nibble of a write to A000 in command mode is the
+/// command and the low nibble its argument.
+///
+/// - 0x1: read the nibble at the address into the response, then advance the address
+/// - 0x3: write the argument at the address, then advance the address
+/// - 0x4/0x5: set the low/high nibble of the address
+/// - 0x6: 0 copies the time to 0x00-0x05, 1 sets the time from there, 2 reports status
+///
+/// The time is minutes since midnight in nibbles 0x00-0x02 and days in 0x03-0x05,
+/// least significant nibble first.
+pub struct Huc3 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    rom_banks: usize,
+    rom_bank: usize,
+    ram_bank: usize,
+    mode: Mode,
+    /// 256 nibbles of the clock chip's memory.
+    rtc_memory: [u8; 256],
+    rtc_address: u8,
+    /// Last command in bits 4-6 and its result in bits 0-3.
+    response: u8,
+    minutes: u16,
+    days: u16,
+    rtc_cycles: u64,
+    led_on: bool,
+}
+
+impl Huc3 {
+    pub fn new(rom: Vec<u8>, ram_size: usize) -> Self {
+        Huc3 {
+            rom_banks: rom_banks(&rom),
+            rom,
+            ram: vec![0; ram_size],
+            rom_bank: 1,
+            ram_bank: 0,
+            mode: Mode::RamReadOnly,
+            rtc_memory: [0; 256],
+            rtc_address: 0,
+            response: 0,
+            minutes: 0,
+            days: 0,
+            rtc_cycles: 0,
+            led_on: false,
+        }
+    }
+
+    fn ram_index(&self, address: u16) -> usize {
+        (self.ram_bank * RAM_BANK_SIZE + (address as usize & 0x1FFF)) % self.ram.len()
+    }
+
+    fn ram_mapped(&self) -> bool {
+        matches!(self.mode, Mode::RamReadOnly | Mode::Ram) && !self.ram.is_empty()
+    }
+
+    fn execute(&mut self, value: u8) {
+        let command = (value >> 4) & 0x07;
+        let argument = value & 0x0F;
+        let mut result = argument;
+        match command {
+            0x1 => {
+                result = self.rtc_memory[self.rtc_address as usize];
+                self.rtc_address = self.rtc_address.wrapping_add(1);
+            }
+            0x3 => {
+                self.rtc_memory[self.rtc_address as usize] = argument;
+                self.rtc_address = self.rtc_address.wrapping_add(1);
+            }
+            0x4 => self.rtc_address = (self.rtc_address & 0xF0) | argument,
+            0x5 => self.rtc_address = (self.rtc_address & 0x0F) | (argument << 4),
+            0x6 => match argument {
+                0x0 => {
+                    let time = self.minutes as u32 | (self.days as u32) << 12;
+                    for nibble in 0..6 {
+                        self.rtc_memory[nibble] = (time >> (nibble * 4)) as u8 & 0x0F;
+                    }
+                }
+                0x1 => {
+                    let time = (0..6).fold(0, |time, nibble| {
+                        time | (self.rtc_memory[nibble] as u32) << (nibble * 4)
+                    });
+                    self.minutes = (time & 0xFFF) as u16 % MINUTES_PER_DAY;
+                    self.days = (time >> 12) as u16 & 0x0FFF;
+                    self.rtc_cycles = 0;
+                }
+                // Status: the clock is running
+                0x2 => result = 0x01,
+                _ => (),
+            },
+            _ => (),
+        }
+        self.response = (command << 4) | result;
+    }
+}
+
+impl Cartridge for Huc3 {
+    fn read_rom(&self, address: u16) -> u8 {
+        let bank = if address < 0x4000 {
+            0
+        } else {
+            self.rom_bank % self.rom_banks
+        };
+        let index = bank * ROM_BANK_SIZE + (address as usize & 0x3FFF);
+        self.rom.get(index).copied().unwrap_or(0xFF)
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..0x2000 => self.mode = Mode::from_value(value),
+            0x2000..0x4000 => self.rom_bank = (value & 0x7F) as usize,
+            0x4000..0x6000 => self.ram_bank = (value & 0x03) as usize,
+            _ => {}
+        }
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        match self.mode {
+            _ if self.ram_mapped() => self.ram[self.ram_index(address)],
+            Mode::Response => 0x80 | self.response,
+            // Commands finish instantly, so the chip is always ready
+            Mode::Semaphore => 0x01,
+            Mode::Ir => IR_DARK,
+            _ => 0xFF,
+        }
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        match self.mode {
+            Mode::Ram if !self.ram.is_empty() => {
+                let index = self.ram_index(address);
+                self.ram[index] = value;
+            }
+            Mode::Command => self.execute(value),
+            Mode::Ir => self.led_on = value & 0x01 != 0,
+            _ => (),
+        }
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        self.rtc_cycles += cycles;
+        while self.rtc_cycles >= CYCLES_PER_MINUTE {
+            self.rtc_cycles -= CYCLES_PER_MINUTE;
+            self.minutes += 1;
+            if self.minutes == MINUTES_PER_DAY {
+                self.minutes = 0;
+                self.days = (self.days + 1) & 0x0FFF;
+            }
+        }
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
+    fn mapped_ram_index(&self, address: u16) -> Option<usize> {
+        self.ram_mapped().then(|| self.ram_index(address))
+    }
+
+    /// RAM followed by the clock as 32-bit minutes and days, then a 64-bit timestamp
+    /// that, like on MBC3, is written but not used on load.
+    fn save_battery(&self) -> Vec<u8> {
+        let mut data = self.ram.clone();
+        data.extend_from_slice(&(self.minutes as u32).to_le_bytes());
+        data.extend_from_slice(&(self.days as u32).to_le_bytes());
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs());
+        data.extend_from_slice(&timestamp.to_le_bytes());
+        data
+    }
+
+    fn load_battery(&mut self, data: &[u8]) {
+        let length = self.ram.len().min(data.len());
+        self.ram[..length].copy_from_slice(&data[..length]);
+
+        let clock = &data[length..];
+        if clock.len() >= 8 {
+            let word =
+                |offset: usize| u32::from_le_bytes(clock[offset..offset + 4].try_into().unwrap());
+            self.minutes = word(0) as u16 % MINUTES_PER_DAY;
+            self.days = word(4) as u16 & 0x0FFF;
+        }
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.ram);
+        state.write_u8(self.rom_bank as u8);
+        state.write_u8(self.ram_bank as u8);
+        state.write_u8(self.mode.to_value());
+        state.write_bytes(&self.rtc_memory);
+        state.write_u8(self.rtc_address);
+        state.write_u8(self.response);
+        state.write_u16(self.minutes);
+        state.write_u16(self.days);
+        state.write_u64(self.rtc_cycles);
+        state.write_bool(self.led_on);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        state.read_bytes_into(&mut self.ram)?;
+        self.rom_bank = state.read_u8()? as usize;
+        self.ram_bank = state.read_u8()? as usize;
+        self.mode = Mode::from_value(state.read_u8()?);
+        state.read_bytes_into(&mut self.rtc_memory)?;
+        self.rtc_address = state.read_u8()?;
+        self.response = state.read_u8()?;
+        self.minutes = state.read_u16()?;
+        self.days = state.read_u16()?;
+        self.rtc_cycles = state.read_u64()?;
+        self.led_on = state.read_bool()?;
+        Ok(())
+    }
+}
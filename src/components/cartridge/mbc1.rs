@@ -0,0 +1,126 @@
+use crate::components::cartridge::header::NINTENDO_LOGO;
+use crate::components::cartridge::{Cartridge, RAM_BANK_SIZE, ROM_BANK_SIZE, rom_banks};
+use crate::io::savestate::{StateError, StateReader, StateWriter};
+
+/// Where the second game's header sits on an MBC1M multicart.
+const MULTICART_HEADER: usize = 0x40000;
+
+/// MBC1, or MBC1M on multicarts: the same chip with BANK2 wired one bit lower, so each
+/// game sees 16 banks of its own.
+pub struct Mbc1 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    rom_banks: usize,
+    ram_enabled: bool,
+    bank1: u8,
+    bank2: u8,
+    banking_mode: u8,
+    /// How far BANK2 is shifted up in the ROM bank number, 4 on multicarts.
+    bank2_shift: u32,
+}
+
+impl Mbc1 {
+    pub fn new(rom: Vec<u8>, ram_size: usize) -> Self {
+        Mbc1 {
+            rom_banks: rom_banks(&rom),
+            ram: vec![0; ram_size],
+            ram_enabled: false,
+            bank1: 1,
+            bank2: 0,
+            banking_mode: 0,
+            bank2_shift: if is_multicart(&rom) { 4 } else { 5 },
+            rom,
+        }
+    }
+
+    fn rom_bank(&self, address: u16) -> usize {
+        let upper = (self.bank2 as usize) << self.bank2_shift;
+        let bank = if address < 0x4000 {
+            if self.banking_mode == 0 { 0 } else { upper }
+        } else {
+            // Only the low bits of BANK1 reach the ROM, but the zero check still sees all 5
+            upper | (self.bank1 as usize & ((1 << self.bank2_shift) - 1))
+        };
+        bank % self.rom_banks
+    }
+
+    fn ram_index(&self, address: u16) -> usize {
+        let bank = if self.banking_mode == 1 {
+            self.bank2 as usize
+        } else {
+            0
+        };
+        (bank * RAM_BANK_SIZE + (address as usize & 0x1FFF)) % self.ram.len()
+    }
+}
+
+/// Multicarts are 8 Mbit and repeat the Nintendo logo in the header of the game at bank
+/// 0x10, which a plain MBC1 game has no reason to do.
+fn is_multicart(rom: &[u8]) -> bool {
+    rom.len() == 0x100000 && rom[MULTICART_HEADER + 0x0104..][..48] == NINTENDO_LOGO
+}
+
+impl Cartridge for Mbc1 {
+    fn read_rom(&self, address: u16) -> u8 {
+        let index = self.rom_bank(address) * ROM_BANK_SIZE + (address as usize & 0x3FFF);
+        self.rom.get(index).copied().unwrap_or(0xFF)
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..0x2000 => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..0x4000 => {
+                self.bank1 = match value & 0x1F {
+                    0 => 1,
+                    n => n,
+                }
+            }
+            0x4000..0x6000 => self.bank2 = value & 0x03,
+            _ => self.banking_mode = value & 0x01,
+        }
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        if !self.ram_enabled || self.ram.is_empty() {
+            return 0xFF;
+        }
+        self.ram[self.ram_index(address)]
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if !self.ram_enabled || self.ram.is_empty() {
+            return;
+        }
+        let index = self.ram_index(address);
+        self.ram[index] = value;
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
+    fn mapped_ram_index(&self, address: u16) -> Option<usize> {
+        (!self.ram.is_empty()).then(|| self.ram_index(address))
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.ram);
+        state.write_bool(self.ram_enabled);
+        state.write_u8(self.bank1);
+        state.write_u8(self.bank2);
+        state.write_u8(self.banking_mode);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        state.read_bytes_into(&mut self.ram)?;
+        self.ram_enabled = state.read_bool()?;
+        self.bank1 = state.read_u8()?;
+        self.bank2 = state.read_u8()?;
+        self.banking_mode = state.read_u8()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,85 @@
+use crate::components::cartridge::{Cartridge, ROM_BANK_SIZE, rom_banks};
+use crate::io::savestate::{StateError, StateReader, StateWriter};
+
+/// Up to 256 KiB of ROM and 512 half-bytes of built-in RAM.
+pub struct Mbc2 {
+    rom: Vec<u8>,
+    ram: [u8; 0x200],
+    rom_banks: usize,
+    ram_enabled: bool,
+    rom_bank: u8,
+}
+
+impl Mbc2 {
+    pub fn new(rom: Vec<u8>) -> Self {
+        Mbc2 {
+            rom_banks: rom_banks(&rom),
+            rom,
+            ram: [0; 0x200],
+            ram_enabled: false,
+            rom_bank: 1,
+        }
+    }
+}
+
+impl Cartridge for Mbc2 {
+    fn read_rom(&self, address: u16) -> u8 {
+        let bank = if address < 0x4000 {
+            0
+        } else {
+            self.rom_bank as usize % self.rom_banks
+        };
+        let index = bank * ROM_BANK_SIZE + (address as usize & 0x3FFF);
+        self.rom.get(index).copied().unwrap_or(0xFF)
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        if address >= 0x4000 {
+            return;
+        }
+        // Address bit 8 tells the RAM enable register apart from the ROM bank register
+        if address & 0x0100 == 0 {
+            self.ram_enabled = value & 0x0F == 0x0A;
+        } else {
+            self.rom_bank = match value & 0x0F {
+                0 => 1,
+                n => n,
+            };
+        }
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        if !self.ram_enabled {
+            return 0xFF;
+        }
+        // Only the lower nibble exists, the upper one reads back as set
+        0xF0 | self.ram[(address & 0x01FF) as usize]
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if self.ram_enabled {
+            self.ram[(address & 0x01FF) as usize] = value & 0x0F;
+        }
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.ram);
+        state.write_bool(self.ram_enabled);
+        state.write_u8(self.rom_bank);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        state.read_bytes_into(&mut self.ram)?;
+        self.ram_enabled = state.read_bool()?;
+        self.rom_bank = state.read_u8()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,257 @@
+use crate::components::cartridge::{Cartridge, RAM_BANK_SIZE, ROM_BANK_SIZE, rom_banks};
+use crate::io::savestate::{StateError, StateReader, StateWriter};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const CYCLES_PER_SECOND: u64 = 4_194_304;
+
+/// Real time clock registers 0x08–0x0C, counted in emulated time.
+#[derive(Clone, Copy, Default)]
+struct Rtc {
+    seconds: u8,
+    minutes: u8,
+    hours: u8,
+    days_low: u8,
+    days_high: u8,
+}
+
+impl Rtc {
+    fn halted(&self) -> bool {
+        self.days_high & 0x40 != 0
+    }
+
+    fn get(&self, register: u8) -> u8 {
+        match register {
+            0x08 => self.seconds,
+            0x09 => self.minutes,
+            0x0A => self.hours,
+            0x0B => self.days_low,
+            _ => self.days_high,
+        }
+    }
+
+    fn set(&mut self, register: u8, value: u8) {
+        match register {
+            0x08 => self.seconds = value & 0x3F,
+            0x09 => self.minutes = value & 0x3F,
+            0x0A => self.hours = value & 0x1F,
+            0x0B => self.days_low = value,
+            _ => self.days_high = value & 0xC1,
+        }
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        for register in 0x08..=0x0C {
+            state.write_u8(self.get(register));
+        }
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        for register in 0x08..=0x0C {
+            self.set(register, state.read_u8()?);
+        }
+        Ok(())
+    }
+
+    fn advance_second(&mut self) {
+        self.seconds = (self.seconds + 1) & 0x3F;
+        if self.seconds != 60 {
+            return;
+        }
+        self.seconds = 0;
+        self.minutes = (self.minutes + 1) & 0x3F;
+        if self.minutes != 60 {
+            return;
+        }
+        self.minutes = 0;
+        self.hours = (self.hours + 1) & 0x1F;
+        if self.hours != 24 {
+            return;
+        }
+        self.hours = 0;
+        let days = ((self.days_high as u16 & 0x01) << 8 | self.days_low as u16) + 1;
+        self.days_low = days as u8;
+        self.days_high = (self.days_high & 0xFE) | ((days >> 8) as u8 & 0x01);
+        if days > 0x1FF {
+            self.days_high |= 0x80;
+        }
+    }
+}
+
+pub struct Mbc3 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    rom_banks: usize,
+    ram_enabled: bool,
+    rom_bank: usize,
+    /// 0x00–0x03 selects a RAM bank, 0x08–0x0C an RTC register
+    ram_bank: u8,
+    has_rtc: bool,
+    rtc: Rtc,
+    latched_rtc: Rtc,
+    latch_armed: bool,
+    rtc_cycles: u64,
+}
+
+impl Mbc3 {
+    pub fn new(rom: Vec<u8>, ram_size: usize, has_rtc: bool) -> Self {
+        Mbc3 {
+            rom_banks: rom_banks(&rom),
+            rom,
+            ram: vec![0; ram_size],
+            ram_enabled: false,
+            rom_bank: 1,
+            ram_bank: 0,
+            has_rtc,
+            rtc: Rtc::default(),
+            latched_rtc: Rtc::default(),
+            latch_armed: false,
+            rtc_cycles: 0,
+        }
+    }
+
+    fn ram_index(&self, address: u16) -> usize {
+        (self.ram_bank as usize * RAM_BANK_SIZE + (address as usize & 0x1FFF)) % self.ram.len()
+    }
+
+    fn rtc_selected(&self) -> bool {
+        self.has_rtc && (0x08..=0x0C).contains(&self.ram_bank)
+    }
+}
+
+impl Cartridge for Mbc3 {
+    fn read_rom(&self, address: u16) -> u8 {
+        let bank = if address < 0x4000 {
+            0
+        } else {
+            self.rom_bank % self.rom_banks
+        };
+        let index = bank * ROM_BANK_SIZE + (address as usize & 0x3FFF);
+        self.rom.get(index).copied().unwrap_or(0xFF)
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..0x2000 => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..0x4000 => {
+                self.rom_bank = match value & 0x7F {
+                    0 => 1,
+                    n => n as usize,
+                }
+            }
+            0x4000..0x6000 => self.ram_bank = value & 0x0F,
+            _ => {
+                if self.latch_armed && value == 0x01 {
+                    self.latched_rtc = self.rtc;
+                }
+                self.latch_armed = value == 0x00;
+            }
+        }
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        if !self.ram_enabled {
+            0xFF
+        } else if self.rtc_selected() {
+            self.latched_rtc.get(self.ram_bank)
+        } else if self.ram_bank < 0x04 && !self.ram.is_empty() {
+            self.ram[self.ram_index(address)]
+        } else {
+            0xFF
+        }
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if !self.ram_enabled {
+            return;
+        }
+        if self.rtc_selected() {
+            if self.ram_bank == 0x08 {
+                self.rtc_cycles = 0;
+            }
+            self.rtc.set(self.ram_bank, value);
+        } else if self.ram_bank < 0x04 && !self.ram.is_empty() {
+            let index = self.ram_index(address);
+            self.ram[index] = value;
+        }
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        if !self.has_rtc || self.rtc.halted() {
+            return;
+        }
+        self.rtc_cycles += cycles;
+        while self.rtc_cycles >= CYCLES_PER_SECOND {
+            self.rtc_cycles -= CYCLES_PER_SECOND;
+            self.rtc.advance_second();
+        }
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
+    fn mapped_ram_index(&self, address: u16) -> Option<usize> {
+        (self.ram_bank < 0x04 && !self.ram.is_empty()).then(|| self.ram_index(address))
+    }
+
+    /// RAM followed by the clock in the layout other emulators use: live and latched
+    /// registers as 32-bit words, then a 64-bit timestamp. The clock runs on emulated
+    /// time, so the timestamp is written but not used on load.
+    fn save_battery(&self) -> Vec<u8> {
+        let mut data = self.ram.clone();
+        if self.has_rtc {
+            for rtc in [&self.rtc, &self.latched_rtc] {
+                for register in 0x08..=0x0C {
+                    data.extend_from_slice(&(rtc.get(register) as u32).to_le_bytes());
+                }
+            }
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |time| time.as_secs());
+            data.extend_from_slice(&timestamp.to_le_bytes());
+        }
+        data
+    }
+
+    fn load_battery(&mut self, data: &[u8]) {
+        let length = self.ram.len().min(data.len());
+        self.ram[..length].copy_from_slice(&data[..length]);
+
+        let clock = &data[length..];
+        if self.has_rtc && clock.len() >= 40 {
+            let mut words = clock.chunks_exact(4).map(|word| word[0]);
+            for rtc in [&mut self.rtc, &mut self.latched_rtc] {
+                for register in 0x08..=0x0C {
+                    rtc.set(register, words.next().unwrap_or(0));
+                }
+            }
+        }
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.ram);
+        state.write_bool(self.ram_enabled);
+        state.write_u8(self.rom_bank as u8);
+        state.write_u8(self.ram_bank);
+        self.rtc.save_state(state);
+        self.latched_rtc.save_state(state);
+        state.write_bool(self.latch_armed);
+        state.write_u64(self.rtc_cycles);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        state.read_bytes_into(&mut self.ram)?;
+        self.ram_enabled = state.read_bool()?;
+        self.rom_bank = state.read_u8()? as usize;
+        self.ram_bank = state.read_u8()?;
+        self.rtc.load_state(state)?;
+        self.latched_rtc.load_state(state)?;
+        self.latch_armed = state.read_bool()?;
+        self.rtc_cycles = state.read_u64()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,110 @@
+use crate::components::cartridge::{Cartridge, RAM_BANK_SIZE, ROM_BANK_SIZE, rom_banks};
+use crate::io::savestate::{StateError, StateReader, StateWriter};
+
+/// MBC5. On rumble carts bit 3 of the RAM bank register drives the motor instead, so
+/// they only have eight RAM banks.
+pub struct Mbc5 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    rom_banks: usize,
+    ram_enabled: bool,
+    rom_bank: usize,
+    ram_bank: usize,
+    has_rumble: bool,
+    rumble: bool,
+}
+
+impl Mbc5 {
+    pub fn new(rom: Vec<u8>, ram_size: usize, has_rumble: bool) -> Self {
+        Mbc5 {
+            rom_banks: rom_banks(&rom),
+            rom,
+            ram: vec![0; ram_size],
+            ram_enabled: false,
+            rom_bank: 1,
+            ram_bank: 0,
+            has_rumble,
+            rumble: false,
+        }
+    }
+
+    fn ram_index(&self, address: u16) -> usize {
+        (self.ram_bank * RAM_BANK_SIZE + (address as usize & 0x1FFF)) % self.ram.len()
+    }
+}
+
+impl Cartridge for Mbc5 {
+    fn read_rom(&self, address: u16) -> u8 {
+        let bank = if address < 0x4000 {
+            0
+        } else {
+            self.rom_bank % self.rom_banks
+        };
+        let index = bank * ROM_BANK_SIZE + (address as usize & 0x3FFF);
+        self.rom.get(index).copied().unwrap_or(0xFF)
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..0x2000 => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..0x3000 => self.rom_bank = (self.rom_bank & 0x100) | value as usize,
+            0x3000..0x4000 => {
+                self.rom_bank = (self.rom_bank & 0x0FF) | ((value as usize & 0x01) << 8)
+            }
+            0x4000..0x6000 if self.has_rumble => {
+                self.ram_bank = (value & 0x07) as usize;
+                self.rumble = value & 0x08 != 0;
+            }
+            0x4000..0x6000 => self.ram_bank = (value & 0x0F) as usize,
+            _ => {}
+        }
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        if !self.ram_enabled || self.ram.is_empty() {
+            return 0xFF;
+        }
+        self.ram[self.ram_index(address)]
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if !self.ram_enabled || self.ram.is_empty() {
+            return;
+        }
+        let index = self.ram_index(address);
+        self.ram[index] = value;
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
+    fn mapped_ram_index(&self, address: u16) -> Option<usize> {
+        (!self.ram.is_empty()).then(|| self.ram_index(address))
+    }
+
+    fn rumble(&self) -> bool {
+        self.rumble
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.ram);
+        state.write_bool(self.ram_enabled);
+        state.write_u16(self.rom_bank as u16);
+        state.write_u8(self.ram_bank as u8);
+        state.write_bool(self.rumble);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        state.read_bytes_into(&mut self.ram)?;
+        self.ram_enabled = state.read_bool()?;
+        self.rom_bank = state.read_u16()? as usize;
+        self.ram_bank = state.read_u8()? as usize;
+        self.rumble = state.read_bool()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,243 @@
+use crate::components::cartridge::Cartridge;
+use crate::io::savestate::{StateError, StateReader, StateWriter};
+
+const WINDOW_SIZE: usize = 0x2000;
+const RAM_WINDOW_SIZE: usize = 0x1000;
+/// MX29F008: 1 MiB, erased in 128 KiB sectors.
+const FLASH_SIZE: usize = 0x100000;
+const FLASH_SECTOR_SIZE: usize = 0x20000;
+/// Manufacturer and device ID, read in ID mode.
+const FLASH_ID: [u8; 2] = [0xC2, 0x81];
+
+/// Where the flash chip is in its command sequence. Commands start with 0xAA to 5555
+/// and 0x55 to 2AAA; erasing needs that unlock twice.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Flash {
+    Ready,
+    Unlocked1,
+    Unlocked2,
+    /// The next write programs a byte.
+    Program,
+    EraseArmed,
+    EraseUnlocked1,
+    EraseUnlocked2,
+    /// Reads return the chip ID until reset with 0xF0.
+    Id,
+}
+
+const FLASH_STATES: [Flash; 8] = [
+    Flash::Ready,
+    Flash::Unlocked1,
+    Flash::Unlocked2,
+    Flash::Program,
+    Flash::EraseArmed,
+    Flash::EraseUnlocked1,
+    Flash::EraseUnlocked2,
+    Flash::Id,
+];
+
+/// MBC6, with two independently switched 8 KiB windows at 4000-5FFF and 6000-7FFF that
+/// each map ROM or flash, and two 4 KiB RAM windows at A000-AFFF and B000-BFFF.
+///
+/// - 0000-03FF: RAM enable
+/// - 0400-07FF / 0800-0BFF: RAM bank of window A / B
+/// - 0C00-0FFF: flash enable, 1000: flash write enable
+/// - 2000-27FF / 3000-37FF: ROM or flash bank of window A / B
+/// - 2800-2FFF / 3800-3FFF: 0x08 maps flash into window A / B instead of ROM
+pub struct Mbc6 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    flash: Vec<u8>,
+    ram_enabled: bool,
+    ram_banks: [u8; 2],
+    rom_banks: [u8; 2],
+    flash_selected: [bool; 2],
+    flash_enabled: bool,
+    flash_write_enabled: bool,
+    flash_state: Flash,
+}
+
+impl Mbc6 {
+    pub fn new(rom: Vec<u8>, ram_size: usize) -> Self {
+        Mbc6 {
+            rom,
+            ram: vec![0; ram_size],
+            flash: vec![0xFF; FLASH_SIZE],
+            ram_enabled: false,
+            ram_banks: [0; 2],
+            rom_banks: [0; 2],
+            flash_selected: [false; 2],
+            flash_enabled: false,
+            flash_write_enabled: false,
+            flash_state: Flash::Ready,
+        }
+    }
+
+    fn window(address: u16) -> usize {
+        (address as usize >> 13) & 0x01
+    }
+
+    fn flash_index(&self, address: u16) -> usize {
+        let bank = self.rom_banks[Self::window(address)] as usize & 0x7F;
+        bank * WINDOW_SIZE + (address as usize & 0x1FFF)
+    }
+
+    fn ram_index(&self, address: u16) -> usize {
+        let window = (address as usize >> 12) & 0x01;
+        (self.ram_banks[window] as usize * RAM_WINDOW_SIZE + (address as usize & 0x0FFF))
+            % self.ram.len()
+    }
+
+    fn write_flash(&mut self, address: u16, value: u8) {
+        let index = self.flash_index(address);
+        // The chip only sees 15 address lines when decoding commands
+        let command_address = index & 0x7FFF;
+        self.flash_state = match (self.flash_state, command_address, value) {
+            (Flash::Program, _, _) => {
+                // Programming can only clear bits; erasing sets them again
+                self.flash[index] &= value;
+                Flash::Ready
+            }
+            (_, _, 0xF0) => Flash::Ready,
+            (Flash::Ready | Flash::Id, 0x5555, 0xAA) => Flash::Unlocked1,
+            (Flash::Unlocked1, 0x2AAA, 0x55) => Flash::Unlocked2,
+            (Flash::Unlocked2, 0x5555, 0xA0) => Flash::Program,
+            (Flash::Unlocked2, 0x5555, 0x80) => Flash::EraseArmed,
+            (Flash::Unlocked2, 0x5555, 0x90) => Flash::Id,
+            (Flash::EraseArmed, 0x5555, 0xAA) => Flash::EraseUnlocked1,
+            (Flash::EraseUnlocked1, 0x2AAA, 0x55) => Flash::EraseUnlocked2,
+            (Flash::EraseUnlocked2, _, 0x30) => {
+                let sector = index / FLASH_SECTOR_SIZE * FLASH_SECTOR_SIZE;
+                self.flash[sector..sector + FLASH_SECTOR_SIZE].fill(0xFF);
+                Flash::Ready
+            }
+            (Flash::EraseUnlocked2, 0x5555, 0x10) => {
+                self.flash.fill(0xFF);
+                Flash::Ready
+            }
+            (Flash::Id, _, _) => Flash::Id,
+            _ => Flash::Ready,
+        };
+    }
+}
+
+impl Cartridge for Mbc6 {
+    fn read_rom(&self, address: u16) -> u8 {
+        if address < 0x4000 {
+            return self.rom.get(address as usize).copied().unwrap_or(0xFF);
+        }
+        let window = Self::window(address);
+        if self.flash_selected[window] {
+            if !self.flash_enabled {
+                return 0xFF;
+            }
+            let index = self.flash_index(address);
+            return match self.flash_state {
+                Flash::Id => FLASH_ID.get(index & 0xFF).copied().unwrap_or(0x00),
+                _ => self.flash[index],
+            };
+        }
+        let banks = self.rom.len().div_ceil(WINDOW_SIZE).max(1);
+        let bank = self.rom_banks[window] as usize % banks;
+        let index = bank * WINDOW_SIZE + (address as usize & 0x1FFF);
+        self.rom.get(index).copied().unwrap_or(0xFF)
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..0x0400 => self.ram_enabled = value & 0x0F == 0x0A,
+            0x0400..0x0800 => self.ram_banks[0] = value & 0x07,
+            0x0800..0x0C00 => self.ram_banks[1] = value & 0x07,
+            0x0C00..0x1000 => self.flash_enabled = value & 0x01 != 0,
+            0x1000 => self.flash_write_enabled = value & 0x01 != 0,
+            0x2000..0x2800 => self.rom_banks[0] = value,
+            0x2800..0x3000 => self.flash_selected[0] = value == 0x08,
+            0x3000..0x3800 => self.rom_banks[1] = value,
+            0x3800..0x4000 => self.flash_selected[1] = value == 0x08,
+            0x4000..0x8000
+                if self.flash_selected[Self::window(address)]
+                    && self.flash_enabled
+                    && self.flash_write_enabled =>
+            {
+                self.write_flash(address, value)
+            }
+            _ => {}
+        }
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        if !self.ram_enabled || self.ram.is_empty() {
+            return 0xFF;
+        }
+        self.ram[self.ram_index(address)]
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if !self.ram_enabled || self.ram.is_empty() {
+            return;
+        }
+        let index = self.ram_index(address);
+        self.ram[index] = value;
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
+    fn mapped_ram_index(&self, address: u16) -> Option<usize> {
+        (!self.ram.is_empty()).then(|| self.ram_index(address))
+    }
+
+    /// RAM followed by the whole flash chip.
+    fn save_battery(&self) -> Vec<u8> {
+        [&self.ram[..], &self.flash[..]].concat()
+    }
+
+    fn load_battery(&mut self, data: &[u8]) {
+        let length = self.ram.len().min(data.len());
+        self.ram[..length].copy_from_slice(&data[..length]);
+
+        let flash = &data[length..];
+        let length = self.flash.len().min(flash.len());
+        self.flash[..length].copy_from_slice(&flash[..length]);
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.ram);
+        state.write_bytes(&self.flash);
+        state.write_bool(self.ram_enabled);
+        for window in 0..2 {
+            state.write_u8(self.ram_banks[window]);
+            state.write_u8(self.rom_banks[window]);
+            state.write_bool(self.flash_selected[window]);
+        }
+        state.write_bool(self.flash_enabled);
+        state.write_bool(self.flash_write_enabled);
+        let flash_state = FLASH_STATES
+            .iter()
+            .position(|&flash_state| flash_state == self.flash_state)
+            .unwrap();
+        state.write_u8(flash_state as u8);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        state.read_bytes_into(&mut self.ram)?;
+        state.read_bytes_into(&mut self.flash)?;
+        self.ram_enabled = state.read_bool()?;
+        for window in 0..2 {
+            self.ram_banks[window] = state.read_u8()?;
+            self.rom_banks[window] = state.read_u8()?;
+            self.flash_selected[window] = state.read_bool()?;
+        }
+        self.flash_enabled = state.read_bool()?;
+        self.flash_write_enabled = state.read_bool()?;
+        self.flash_state = *FLASH_STATES
+            .get(state.read_u8()? as usize)
+            .ok_or(StateError::Mismatch("MBC6 flash state"))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,312 @@
+use crate::components::cartridge::{Cartridge, ROM_BANK_SIZE, rom_banks};
+use crate::io::savestate::{StateError, StateReader, StateWriter};
+
+/// 93LC56: 128 words of 16 bits.
+const EEPROM_SIZE: usize = 256;
+/// Accelerometer reading when level; one g moves it by `ACCELEROMETER_G`.
+const ACCELEROMETER_CENTER: f32 = 0x81D0 as f32;
+const ACCELEROMETER_G: f32 = 0x70 as f32;
+
+const CS: u8 = 0x80;
+const CLK: u8 = 0x40;
+const DI: u8 = 0x02;
+
+/// What the EEPROM expects on the next rising clock edge.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Eeprom {
+    /// Waiting for the start bit.
+    Idle,
+    /// Shifting in the 2 opcode and 8 address bits.
+    Command,
+    /// Shifting out a word, most significant bit first.
+    Read,
+    /// Shifting in the word for WRITE (`Some(address)`) or WRAL (`None`).
+    Write(Option<u8>),
+    /// Done until CS goes low.
+    Finished,
+}
+
+/// MBC7, with an ADXL202E accelerometer and a 93LC56 serial EEPROM in place of RAM.
+///
+/// Both RAM enables (0x0A to 0000-1FFF, 0x40 to 4000-5FFF) map the registers to
+/// A000-AFFF, one per 0x10 bytes:
+///
+/// - Ax0x: 0x55 resets the latched values to 0x8000
+/// - Ax1x: 0xAA then latches the accelerometer
+/// - Ax2x-Ax5x: X low, X high, Y low, Y high
+/// - Ax8x: EEPROM pins, CS (bit 7), CLK (bit 6), DI (bit 1) and DO (bit 0)
+pub struct Mbc7 {
+    rom: Vec<u8>,
+    rom_banks: usize,
+    rom_bank: usize,
+    ram_enabled: bool,
+    registers_enabled: bool,
+    /// Current tilt in g, right and down positive.
+    tilt: (f32, f32),
+    latched: (u16, u16),
+    latch_ready: bool,
+    /// Words stored little endian, which is also the save file format.
+    eeprom: Vec<u8>,
+    eeprom_state: Eeprom,
+    eeprom_pins: u8,
+    eeprom_out: bool,
+    eeprom_write_enabled: bool,
+    shift: u16,
+    bits: u8,
+}
+
+impl Mbc7 {
+    pub fn new(rom: Vec<u8>) -> Self {
+        Mbc7 {
+            rom_banks: rom_banks(&rom),
+            rom,
+            rom_bank: 1,
+            ram_enabled: false,
+            registers_enabled: false,
+            tilt: (0.0, 0.0),
+            latched: (0x8000, 0x8000),
+            latch_ready: false,
+            eeprom: vec![0xFF; EEPROM_SIZE],
+            eeprom_state: Eeprom::Idle,
+            eeprom_pins: 0,
+            eeprom_out: true,
+            eeprom_write_enabled: false,
+            shift: 0,
+            bits: 0,
+        }
+    }
+
+    fn word(&self, address: u8) -> u16 {
+        let index = (address as usize & 0x7F) * 2;
+        u16::from_le_bytes([self.eeprom[index], self.eeprom[index + 1]])
+    }
+
+    fn set_word(&mut self, address: u8, value: u16) {
+        if self.eeprom_write_enabled {
+            let index = (address as usize & 0x7F) * 2;
+            self.eeprom[index..index + 2].copy_from_slice(&value.to_le_bytes());
+        }
+    }
+
+    fn write_eeprom_pins(&mut self, value: u8) {
+        let rising = self.eeprom_pins & CLK == 0 && value & CLK != 0;
+        self.eeprom_pins = value & (CS | CLK | DI);
+        if value & CS == 0 {
+            self.eeprom_state = Eeprom::Idle;
+            return;
+        }
+        if !rising {
+            return;
+        }
+
+        let bit = (value & DI != 0) as u16;
+        match self.eeprom_state {
+            Eeprom::Idle if bit == 1 => {
+                self.eeprom_state = Eeprom::Command;
+                self.shift = 0;
+                self.bits = 0;
+            }
+            Eeprom::Idle | Eeprom::Finished => (),
+            Eeprom::Command => {
+                self.shift = (self.shift << 1) | bit;
+                self.bits += 1;
+                if self.bits == 10 {
+                    self.execute_command();
+                }
+            }
+            Eeprom::Read => {
+                self.eeprom_out = self.shift & 0x8000 != 0;
+                self.shift <<= 1;
+                self.bits += 1;
+                if self.bits == 16 {
+                    self.eeprom_state = Eeprom::Finished;
+                }
+            }
+            Eeprom::Write(address) => {
+                self.shift = (self.shift << 1) | bit;
+                self.bits += 1;
+                if self.bits == 16 {
+                    match address {
+                        Some(address) => self.set_word(address, self.shift),
+                        None => (0..0x80).for_each(|address| self.set_word(address, self.shift)),
+                    }
+                    // Writes finish instantly, so DO reports ready straight away
+                    self.eeprom_out = true;
+                    self.eeprom_state = Eeprom::Finished;
+                }
+            }
+        }
+    }
+
+    fn execute_command(&mut self) {
+        let opcode = self.shift >> 8;
+        let address = self.shift as u8;
+        self.shift = 0;
+        self.bits = 0;
+        self.eeprom_state = match (opcode, address >> 6) {
+            // READ starts with a dummy 0
+            (0b10, _) => {
+                self.shift = self.word(address);
+                self.eeprom_out = false;
+                Eeprom::Read
+            }
+            (0b01, _) => Eeprom::Write(Some(address)),
+            (0b11, _) => {
+                self.set_word(address, 0xFFFF);
+                self.eeprom_out = true;
+                Eeprom::Finished
+            }
+            (_, 0b11) => {
+                self.eeprom_write_enabled = true;
+                Eeprom::Finished
+            }
+            (_, 0b00) => {
+                self.eeprom_write_enabled = false;
+                Eeprom::Finished
+            }
+            (_, 0b10) => {
+                (0..0x80).for_each(|address| self.set_word(address, 0xFFFF));
+                self.eeprom_out = true;
+                Eeprom::Finished
+            }
+            _ => Eeprom::Write(None),
+        };
+    }
+
+    fn accelerometer(tilt: f32) -> u16 {
+        (ACCELEROMETER_CENTER + tilt * ACCELEROMETER_G) as u16
+    }
+}
+
+impl Cartridge for Mbc7 {
+    fn read_rom(&self, address: u16) -> u8 {
+        let bank = if address < 0x4000 {
+            0
+        } else {
+            self.rom_bank % self.rom_banks
+        };
+        let index = bank * ROM_BANK_SIZE + (address as usize & 0x3FFF);
+        self.rom.get(index).copied().unwrap_or(0xFF)
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..0x2000 => {
+                self.ram_enabled = value == 0x0A;
+                if !self.ram_enabled {
+                    self.registers_enabled = false;
+                }
+            }
+            0x2000..0x4000 => self.rom_bank = (value & 0x7F) as usize,
+            0x4000..0x6000 => self.registers_enabled = self.ram_enabled && value == 0x40,
+            _ => {}
+        }
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        if !self.registers_enabled || address >= 0xB000 {
+            return 0xFF;
+        }
+        match (address >> 4) & 0x0F {
+            0x2 => self.latched.0 as u8,
+            0x3 => (self.latched.0 >> 8) as u8,
+            0x4 => self.latched.1 as u8,
+            0x5 => (self.latched.1 >> 8) as u8,
+            0x6 => 0x00,
+            0x8 => self.eeprom_pins | self.eeprom_out as u8,
+            _ => 0xFF,
+        }
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if !self.registers_enabled || address >= 0xB000 {
+            return;
+        }
+        match (address >> 4) & 0x0F {
+            0x0 if value == 0x55 => {
+                self.latched = (0x8000, 0x8000);
+                self.latch_ready = true;
+            }
+            0x1 if value == 0xAA && self.latch_ready => {
+                self.latched = (
+                    Self::accelerometer(self.tilt.0),
+                    Self::accelerometer(self.tilt.1),
+                );
+                self.latch_ready = false;
+            }
+            0x8 => self.write_eeprom_pins(value),
+            _ => (),
+        }
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.eeprom
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.eeprom
+    }
+
+    /// The EEPROM is not memory mapped.
+    fn mapped_ram_index(&self, _address: u16) -> Option<usize> {
+        None
+    }
+
+    fn set_tilt(&mut self, x: f32, y: f32) {
+        self.tilt = (x, y);
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.eeprom);
+        state.write_u8(self.rom_bank as u8);
+        state.write_bool(self.ram_enabled);
+        state.write_bool(self.registers_enabled);
+        state.write_u16(self.latched.0);
+        state.write_u16(self.latched.1);
+        state.write_bool(self.latch_ready);
+        let eeprom_state = match self.eeprom_state {
+            Eeprom::Idle => 0,
+            Eeprom::Command => 1,
+            Eeprom::Read => 2,
+            Eeprom::Write(Some(_)) => 3,
+            Eeprom::Write(None) => 4,
+            Eeprom::Finished => 5,
+        };
+        state.write_u8(eeprom_state);
+        state.write_u8(match self.eeprom_state {
+            Eeprom::Write(Some(address)) => address,
+            _ => 0,
+        });
+        state.write_u8(self.eeprom_pins);
+        state.write_bool(self.eeprom_out);
+        state.write_bool(self.eeprom_write_enabled);
+        state.write_u16(self.shift);
+        state.write_u8(self.bits);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        state.read_bytes_into(&mut self.eeprom)?;
+        self.rom_bank = state.read_u8()? as usize;
+        self.ram_enabled = state.read_bool()?;
+        self.registers_enabled = state.read_bool()?;
+        self.latched = (state.read_u16()?, state.read_u16()?);
+        self.latch_ready = state.read_bool()?;
+        let eeprom_state = state.read_u8()?;
+        let address = state.read_u8()?;
+        self.eeprom_state = match eeprom_state {
+            0 => Eeprom::Idle,
+            1 => Eeprom::Command,
+            2 => Eeprom::Read,
+            3 => Eeprom::Write(Some(address)),
+            4 => Eeprom::Write(None),
+            5 => Eeprom::Finished,
+            _ => return Err(StateError::Mismatch("MBC7 EEPROM state")),
+        };
+        self.eeprom_pins = state.read_u8()?;
+        self.eeprom_out = state.read_bool()?;
+        self.eeprom_write_enabled = state.read_bool()?;
+        self.shift = state.read_u16()?;
+        self.bits = state.read_u8()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,187 @@
+use crate::components::cartridge::{Cartridge, RAM_BANK_SIZE, ROM_BANK_SIZE, rom_banks};
+use crate::io::savestate::{StateError, StateReader, StateWriter};
+
+/// MMM01, the mapper of multi-game compilations. It powers on unmapped, showing the
+/// menu in the last 32 KiB of ROM. The menu sets up the bank registers of the chosen
+/// game and writes bit 6 to 0000-1FFF, which locks everything except the MBC1-style
+/// registers the game itself uses:
+///
+/// - 0000-1FFF: RAM enable; bits 4-5 the RAM bank mask, bit 6 maps the game
+/// - 2000-3FFF: ROM bank bits 0-4; bits 5-6 are ROM bank bits 5-6
+/// - 4000-5FFF: RAM bank bits 0-1; bits 2-3 RAM bank bits 2-3, bits 4-5 ROM bank bits
+///   7-8, bit 6 locks the banking mode
+/// - 6000-7FFF: banking mode; bits 2-5 the ROM bank mask
+///
+/// A set bit in a mask keeps that bank bit at what the menu wrote, so the game can only
+/// switch between its own banks.
+pub struct Mmm01 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    rom_banks: usize,
+    ram_enabled: bool,
+    mapped: bool,
+    rom_bank_low: u8,
+    rom_bank_mid: u8,
+    rom_bank_high: u8,
+    /// Fixed bits of `rom_bank_low`, 1-4 only.
+    rom_mask: u8,
+    ram_bank_low: u8,
+    ram_bank_high: u8,
+    ram_mask: u8,
+    banking_mode: u8,
+    mode_locked: bool,
+}
+
+impl Mmm01 {
+    pub fn new(rom: Vec<u8>, ram_size: usize) -> Self {
+        Mmm01 {
+            rom_banks: rom_banks(&rom),
+            rom,
+            ram: vec![0; ram_size],
+            ram_enabled: false,
+            mapped: false,
+            rom_bank_low: 1,
+            rom_bank_mid: 0,
+            rom_bank_high: 0,
+            rom_mask: 0,
+            ram_bank_low: 0,
+            ram_bank_high: 0,
+            ram_mask: 0,
+            banking_mode: 0,
+            mode_locked: false,
+        }
+    }
+
+    fn rom_bank(&self, address: u16) -> usize {
+        if !self.mapped {
+            // All bank lines are pulled high, leaving the last two banks
+            return (0x1FE | (address >= 0x4000) as usize) % self.rom_banks;
+        }
+        let upper = (self.rom_bank_high as usize) << 7 | (self.rom_bank_mid as usize) << 5;
+        let bank = if address < 0x4000 {
+            upper | (self.rom_bank_low & self.rom_mask) as usize
+        } else {
+            upper | self.rom_bank_low as usize
+        };
+        bank % self.rom_banks
+    }
+
+    fn ram_index(&self, address: u16) -> usize {
+        let low = if self.banking_mode == 1 {
+            self.ram_bank_low
+        } else {
+            self.ram_bank_low & self.ram_mask
+        };
+        let bank = (self.ram_bank_high << 2 | low) as usize;
+        (bank * RAM_BANK_SIZE + (address as usize & 0x1FFF)) % self.ram.len()
+    }
+
+    /// Once mapped, bits the menu fixed keep their value; the rest come from `value`.
+    fn merge(&self, current: u8, value: u8, mask: u8) -> u8 {
+        let mask = if self.mapped { mask } else { 0 };
+        (current & mask) | (value & !mask)
+    }
+}
+
+impl Cartridge for Mmm01 {
+    fn read_rom(&self, address: u16) -> u8 {
+        let index = self.rom_bank(address) * ROM_BANK_SIZE + (address as usize & 0x3FFF);
+        self.rom.get(index).copied().unwrap_or(0xFF)
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..0x2000 => {
+                self.ram_enabled = value & 0x0F == 0x0A;
+                if !self.mapped {
+                    self.ram_mask = (value >> 4) & 0x03;
+                    self.mapped = value & 0x40 != 0;
+                }
+            }
+            0x2000..0x4000 => {
+                let bank = match value & 0x1F {
+                    0 => 1,
+                    n => n,
+                };
+                self.rom_bank_low = self.merge(self.rom_bank_low, bank, self.rom_mask);
+                if !self.mapped {
+                    self.rom_bank_mid = (value >> 5) & 0x03;
+                }
+            }
+            0x4000..0x6000 => {
+                self.ram_bank_low = self.merge(self.ram_bank_low, value & 0x03, self.ram_mask);
+                if !self.mapped {
+                    self.ram_bank_high = (value >> 2) & 0x03;
+                    self.rom_bank_high = (value >> 4) & 0x03;
+                    self.mode_locked = value & 0x40 != 0;
+                }
+            }
+            _ => {
+                if !self.mode_locked {
+                    self.banking_mode = value & 0x01;
+                }
+                if !self.mapped {
+                    self.rom_mask = (value >> 1) & 0x1E;
+                }
+            }
+        }
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        if !self.ram_enabled || self.ram.is_empty() {
+            return 0xFF;
+        }
+        self.ram[self.ram_index(address)]
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if !self.ram_enabled || self.ram.is_empty() {
+            return;
+        }
+        let index = self.ram_index(address);
+        self.ram[index] = value;
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
+    fn mapped_ram_index(&self, address: u16) -> Option<usize> {
+        (!self.ram.is_empty()).then(|| self.ram_index(address))
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.ram);
+        state.write_bool(self.ram_enabled);
+        state.write_bool(self.mapped);
+        state.write_u8(self.rom_bank_low);
+        state.write_u8(self.rom_bank_mid);
+        state.write_u8(self.rom_bank_high);
+        state.write_u8(self.rom_mask);
+        state.write_u8(self.ram_bank_low);
+        state.write_u8(self.ram_bank_high);
+        state.write_u8(self.ram_mask);
+        state.write_u8(self.banking_mode);
+        state.write_bool(self.mode_locked);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        state.read_bytes_into(&mut self.ram)?;
+        self.ram_enabled = state.read_bool()?;
+        self.mapped = state.read_bool()?;
+        self.rom_bank_low = state.read_u8()?;
+        self.rom_bank_mid = state.read_u8()?;
+        self.rom_bank_high = state.read_u8()?;
+        self.rom_mask = state.read_u8()?;
+        self.ram_bank_low = state.read_u8()?;
+        self.ram_bank_high = state.read_u8()?;
+        self.ram_mask = state.read_u8()?;
+        self.banking_mode = state.read_u8()?;
+        self.mode_locked = state.read_bool()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,54 @@
+use crate::components::cartridge::Cartridge;
+use crate::io::savestate::{StateError, StateReader, StateWriter};
+
+/// 32 KiB of ROM mapped directly, with an optional 8 KiB of RAM (types 0x08/0x09).
+pub struct NoMbc {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+}
+
+impl NoMbc {
+    pub fn new(rom: Vec<u8>, ram_size: usize) -> Self {
+        NoMbc {
+            rom,
+            ram: vec![0; ram_size],
+        }
+    }
+}
+
+impl Cartridge for NoMbc {
+    fn read_rom(&self, address: u16) -> u8 {
+        self.rom.get(address as usize).copied().unwrap_or(0xFF)
+    }
+
+    fn write_rom(&mut self, _address: u16, _value: u8) {}
+
+    fn read_ram(&self, address: u16) -> u8 {
+        self.ram
+            .get((address & 0x1FFF) as usize)
+            .copied()
+            .unwrap_or(0xFF)
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if let Some(byte) = self.ram.get_mut((address & 0x1FFF) as usize) {
+            *byte = value;
+        }
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.ram);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        state.read_bytes_into(&mut self.ram)
+    }
+}
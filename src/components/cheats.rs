@@ -0,0 +1,172 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+/// A Game Genie patch or GameShark write, parsed from the code printed in cheat books.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Cheat {
+    /// Replaces ROM reads at `address`, only while the mapped byte equals `compare`.
+    GameGenie {
+        address: u16,
+        value: u8,
+        compare: Option<u8>,
+    },
+    /// Writes `value` to `address` every frame. For cartridge RAM, `bank` picks the bank
+    /// regardless of what is mapped; `None` writes into the current one.
+    GameShark {
+        bank: Option<u8>,
+        address: u16,
+        value: u8,
+    },
+}
+
+#[derive(Debug, PartialEq)]
+pub enum CheatError {
+    /// Not 6 or 9 (Game Genie) or 8 (GameShark) hex digits.
+    Length(String),
+    Digit(String),
+    /// A Game Genie code that patches outside the ROM.
+    Address(String),
+    /// An error in a cheat file.
+    Line(usize, Box<CheatError>),
+}
+
+impl fmt::Display for CheatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheatError::Length(code) => write!(f, "Cheat code {code} has the wrong length"),
+            CheatError::Digit(code) => write!(f, "Cheat code {code} is not hexadecimal"),
+            CheatError::Address(code) => write!(f, "Game Genie code {code} does not patch ROM"),
+            CheatError::Line(line, error) => write!(f, "Line {line}: {error}"),
+        }
+    }
+}
+
+impl Error for CheatError {}
+
+impl FromStr for Cheat {
+    type Err = CheatError;
+
+    /// Game Genie codes look like `ABC-DEF-GHI` or `ABC-DEF`, GameShark codes like
+    /// `01FFE1C0`.
+    fn from_str(code: &str) -> Result<Self, Self::Err> {
+        let digits = code
+            .chars()
+            .filter(|&c| c != '-')
+            .map(|c| c.to_digit(16).map(|digit| digit as u8))
+            .collect::<Option<Vec<u8>>>()
+            .ok_or_else(|| CheatError::Digit(code.to_string()))?;
+        let byte = |index: usize| (digits[index] << 4) | digits[index + 1];
+
+        match digits.len() {
+            6 | 9 => {
+                let address = ((digits[5] as u16 ^ 0xF) << 12)
+                    | ((digits[2] as u16) << 8)
+                    | ((digits[3] as u16) << 4)
+                    | digits[4] as u16;
+                if address >= 0x8000 {
+                    return Err(CheatError::Address(code.to_string()));
+                }
+                // The compare byte is digits G and I, scrambled; H is not used
+                let compare = (digits.len() == 9)
+                    .then(|| ((digits[6] << 4) | digits[8]).rotate_right(2) ^ 0xBA);
+                Ok(Cheat::GameGenie {
+                    address,
+                    value: byte(0),
+                    compare,
+                })
+            }
+            8 => {
+                // 0x80-0x8F select a cartridge RAM bank, anything else writes to what is mapped
+                let bank = match byte(0) {
+                    bank @ 0x80..=0x8F => Some(bank & 0x0F),
+                    _ => None,
+                };
+                Ok(Cheat::GameShark {
+                    bank,
+                    address: u16::from_le_bytes([byte(4), byte(6)]),
+                    value: byte(2),
+                })
+            }
+            _ => Err(CheatError::Length(code.to_string())),
+        }
+    }
+}
+
+/// Parses a cheat file: one code per line, optionally followed by a description, with
+/// `#` starting a comment.
+pub fn parse_list(text: &str) -> Result<Vec<Cheat>, CheatError> {
+    text.lines()
+        .enumerate()
+        .filter_map(|(index, line)| {
+            let line = line.split('#').next().unwrap_or("");
+            let code = line.split_whitespace().next()?;
+            Some(
+                code.parse()
+                    .map_err(|error| CheatError::Line(index + 1, Box::new(error))),
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_game_genie_codes() {
+        assert_eq!(
+            "00A-17B-C49".parse(),
+            Ok(Cheat::GameGenie {
+                address: 0x4A17,
+                value: 0x00,
+                compare: Some(0xC8),
+            })
+        );
+        assert_eq!(
+            "3EF-DFF".parse(),
+            Ok(Cheat::GameGenie {
+                address: 0x0FDF,
+                value: 0x3E,
+                compare: None,
+            })
+        );
+        assert!(matches!(
+            "00A-170-C49".parse::<Cheat>(),
+            Err(CheatError::Address(_))
+        ));
+    }
+
+    #[test]
+    fn parses_game_shark_codes() {
+        assert_eq!(
+            "01FFE1C0".parse(),
+            Ok(Cheat::GameShark {
+                bank: None,
+                address: 0xC0E1,
+                value: 0xFF,
+            })
+        );
+        assert_eq!(
+            "8263FFA0".parse(),
+            Ok(Cheat::GameShark {
+                bank: Some(2),
+                address: 0xA0FF,
+                value: 0x63,
+            })
+        );
+    }
+
+    #[test]
+    fn cheat_files_report_the_bad_line() {
+        let text = "# Infinite lives\n01FFE1C0 lives\n\n3EF-DFF\n";
+        assert_eq!(parse_list(text).unwrap().len(), 2);
+        assert_eq!(
+            parse_list("01FFE1C0\n01FFE1C"),
+            Err(CheatError::Line(
+                2,
+                Box::new(CheatError::Length("01FFE1C".to_string()))
+            ))
+        );
+    }
+}
@@ -1,5 +1,6 @@
-use crate::components::memory::Memory;
+use crate::components::bus::Bus;
 use crate::components::registers::Registers;
+use crate::io::savestate::{StateError, StateReader, StateWriter};
 
 pub struct CPU {
     pub(crate) registers: Registers,
@@ -7,6 +8,8 @@ pub struct CPU {
     pub(crate) ime: bool,
     ime_pending: u8,
     pub(crate) halted: bool,
+    /// Set by STOP until a selected joypad line goes low.
+    pub(crate) stopped: bool,
 }
 
 impl CPU {
@@ -17,6 +20,7 @@ impl CPU {
             ime: false,
             ime_pending: 0,
             halted: false,
+            stopped: false,
         }
     }
 
@@ -24,6 +28,59 @@ impl CPU {
         self.debug_registers = !self.debug_registers
     }
 
+    pub(crate) fn is_debugging(&self) -> bool {
+        self.debug_registers
+    }
+
+    pub(crate) fn print_registers<B: Bus>(&self, memory: &mut B) {
+        println!(
+            "A: {:02X} F: {:02X} B: {:02X} C: {:02X} D: {:02X} E: {:02X} H: {:02X} L: {:02X} SP: {:04X} PC: 00:{:04X} ({:02X} {:02X} {:02X} {:02X})",
+            self.registers.a,
+            self.registers.f,
+            self.registers.b,
+            self.registers.c,
+            self.registers.d,
+            self.registers.e,
+            self.registers.h,
+            self.registers.l,
+            self.registers.sp,
+            self.registers.pc,
+            memory.read(self.registers.pc),
+            memory.read(self.registers.pc.wrapping_add(1)),
+            memory.read(self.registers.pc.wrapping_add(2)),
+            memory.read(self.registers.pc.wrapping_add(3))
+        );
+    }
+
+    pub(crate) fn save_state(&self, state: &mut StateWriter) {
+        let r = &self.registers;
+        for value in [r.a, r.f, r.b, r.c, r.d, r.e, r.h, r.l] {
+            state.write_u8(value);
+        }
+        state.write_u16(r.sp);
+        state.write_u16(r.pc);
+        state.write_bool(self.ime);
+        state.write_u8(self.ime_pending);
+        state.write_bool(self.halted);
+        state.write_bool(self.stopped);
+    }
+
+    pub(crate) fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        let r = &mut self.registers;
+        for value in [
+            &mut r.a, &mut r.f, &mut r.b, &mut r.c, &mut r.d, &mut r.e, &mut r.h, &mut r.l,
+        ] {
+            *value = state.read_u8()?;
+        }
+        r.sp = state.read_u16()?;
+        r.pc = state.read_u16()?;
+        self.ime = state.read_bool()?;
+        self.ime_pending = state.read_u8()?;
+        self.halted = state.read_bool()?;
+        self.stopped = state.read_bool()?;
+        Ok(())
+    }
+
     pub(crate) fn update_ime(&mut self) {
         if self.ime_pending > 0 {
             self.ime_pending -= 1;
@@ -33,68 +90,38 @@ impl CPU {
         }
     }
 
-    pub fn check_interrupts(&mut self, memory: &mut Memory) {
+    pub fn check_interrupts<B: Bus>(&mut self, memory: &mut B) {
         if self.ime {
-            if let Some(ie) = memory.get(0xFFFF) {
-                if let Some(if_) = memory.get(0xFF0F) {
-                    let ie = *ie;
-                    let if_ = *if_;
-                    let pending = ie & if_;
-
-                    if pending != 0 {
-                        let vector = match pending.trailing_zeros() {
-                            0 => 0x40, // VBlank
-                            1 => 0x48, // LCD STAT
-                            2 => 0x50, // Timer
-                            3 => 0x58, // Serial
-                            4 => 0x60, // Joypad
-                            _ => unreachable!(),
-                        };
-
-                        let high = (self.registers.pc >> 8) as u8;
-                        let low = self.registers.pc as u8;
-                        self.registers.sp = self.registers.sp.wrapping_sub(1);
-                        memory.write_memory(self.registers.sp as usize, high);
-                        self.registers.sp = self.registers.sp.wrapping_sub(1);
-                        memory.write_memory(self.registers.sp as usize, low);
-                        self.registers.pc = vector;
-                        memory.write_memory(0xFF0F, if_ & !(1 << pending.trailing_zeros()));
-                        self.ime = false;
-                        self.halted = false;
-                    }
-                }
+            let ie = memory.read(0xFFFF);
+            let if_ = memory.read(0xFF0F);
+            let pending = ie & if_;
+
+            if pending != 0 {
+                let vector = match pending.trailing_zeros() {
+                    0 => 0x40, // VBlank
+                    1 => 0x48, // LCD STAT
+                    2 => 0x50, // Timer
+                    3 => 0x58, // Serial
+                    4 => 0x60, // Joypad
+                    _ => unreachable!(),
+                };
+
+                let high = (self.registers.pc >> 8) as u8;
+                let low = self.registers.pc as u8;
+                self.registers.sp = self.registers.sp.wrapping_sub(1);
+                memory.write(self.registers.sp, high);
+                self.registers.sp = self.registers.sp.wrapping_sub(1);
+                memory.write(self.registers.sp, low);
+                self.registers.pc = vector;
+                memory.write(0xFF0F, if_ & !(1 << pending.trailing_zeros()));
+                self.ime = false;
+                self.halted = false;
             }
         }
     }
 
     #[allow(unreachable_patterns)]
-    pub(crate) fn process_opcode(&mut self, opcode: u8, memory: &mut Memory) -> (bool, u64) {
-        if self.debug_registers {
-            println!(
-                "A: {:02X} F: {:02X} B: {:02X} C: {:02X} D: {:02X} E: {:02X} H: {:02X} L: {:02X} SP: {:04X} PC: 00:{:04X} ({:02X} {:02X} {:02X} {:02X})",
-                self.registers.a,
-                self.registers.f,
-                self.registers.b,
-                self.registers.c,
-                self.registers.d,
-                self.registers.e,
-                self.registers.h,
-                self.registers.l,
-                self.registers.sp,
-                self.registers.pc,
-                *memory.get(self.registers.pc as usize).unwrap(),
-                memory
-                    .get(self.registers.pc.wrapping_add(1) as usize)
-                    .unwrap(),
-                memory
-                    .get(self.registers.pc.wrapping_add(2) as usize)
-                    .unwrap(),
-                memory
-                    .get(self.registers.pc.wrapping_add(3) as usize)
-                    .unwrap()
-            );
-        }
-
+    pub(crate) fn process_opcode<B: Bus>(&mut self, opcode: u8, memory: &mut B) -> (bool, u64) {
         match opcode {
             0x00 => (false, 4),
             0x01 => {
@@ -102,7 +129,7 @@ impl CPU {
                 (false, 12)
             }
             0x02 => {
-                memory.write_memory(self.registers.get_bc() as usize, self.registers.a);
+                memory.write(self.registers.get_bc(), self.registers.a);
                 (false, 8)
             }
             0x03 => {
@@ -133,24 +160,12 @@ impl CPU {
             }
             0x08 => {
                 self.registers.pc = self.registers.pc.wrapping_add(1);
-                if let Some(low) = memory.get(self.registers.pc as usize) {
-                    self.registers.pc = self.registers.pc.wrapping_add(1);
-                    if let Some(high) = memory.get(self.registers.pc as usize) {
-                        let address = ((*high as u16) << 8) | (*low as u16);
-                        memory.write_memory(address as usize, self.registers.sp as u8);
-                        memory.write_memory((address + 1) as usize, (self.registers.sp >> 8) as u8);
-                    } else {
-                        eprintln!(
-                            "Failed to get high value of a16 at PC {:#06X}",
-                            self.registers.pc
-                        );
-                    }
-                } else {
-                    eprintln!(
-                        "Failed to get low value of a16 at PC {:#06X}",
-                        self.registers.pc
-                    );
-                }
+                let low = memory.read(self.registers.pc);
+                self.registers.pc = self.registers.pc.wrapping_add(1);
+                let high = memory.read(self.registers.pc);
+                let address = ((high as u16) << 8) | (low as u16);
+                memory.write(address, self.registers.sp as u8);
+                memory.write(address.wrapping_add(1), (self.registers.sp >> 8) as u8);
                 (false, 20)
             }
             0x09 => {
@@ -158,11 +173,7 @@ impl CPU {
                 (false, 8)
             }
             0x0A => {
-                if let Some(value) = memory.get(self.registers.get_bc() as usize) {
-                    self.registers.a = *value;
-                } else {
-                    eprintln!("Failed to get value at BC {:#06X}", self.registers.get_bc());
-                }
+                self.registers.a = memory.read(self.registers.get_bc());
                 (false, 8)
             }
             0x0B => {
@@ -192,14 +203,18 @@ impl CPU {
                 (false, 4)
             }
             0x10 => {
-                panic!("STOP");
+                // STOP is followed by a padding byte and resets DIV
+                self.registers.pc = self.registers.pc.wrapping_add(1);
+                memory.write(0xFF04, 0);
+                self.stopped = true;
+                (false, 4)
             }
             0x11 => {
                 self.ld_r16_n16(memory, opcode);
                 (false, 12)
             }
             0x12 => {
-                memory.write_memory(self.registers.get_de() as usize, self.registers.a);
+                memory.write(self.registers.get_de(), self.registers.a);
                 (false, 8)
             }
             0x13 => {
@@ -240,11 +255,7 @@ impl CPU {
                 (false, 8)
             }
             0x1A => {
-                if let Some(value) = memory.get(self.registers.get_de() as usize) {
-                    self.registers.a = *value;
-                } else {
-                    eprintln!("Failed to get value at DE {:#06X}", self.registers.get_de());
-                }
+                self.registers.a = memory.read(self.registers.get_de());
                 (false, 8)
             }
             0x1B => {
@@ -288,7 +299,7 @@ impl CPU {
                 (false, 12)
             }
             0x22 => {
-                memory.write_memory(self.registers.get_hl() as usize, self.registers.a);
+                memory.write(self.registers.get_hl(), self.registers.a);
                 self.registers
                     .set_hl(self.registers.get_hl().wrapping_add(1));
                 (false, 8)
@@ -347,13 +358,9 @@ impl CPU {
                 (false, 8)
             }
             0x2A => {
-                if let Some(value) = memory.get(self.registers.get_hl() as usize) {
-                    self.registers.a = *value;
-                    self.registers
-                        .set_hl(self.registers.get_hl().wrapping_add(1));
-                } else {
-                    eprintln!("Failed to get value at HL {:#06X}", self.registers.get_hl());
-                }
+                self.registers.a = memory.read(self.registers.get_hl());
+                self.registers
+                    .set_hl(self.registers.get_hl().wrapping_add(1));
                 (false, 8)
             }
             0x2B => {
@@ -393,7 +400,7 @@ impl CPU {
                 (false, 12)
             }
             0x32 => {
-                memory.write_memory(self.registers.get_hl() as usize, self.registers.a);
+                memory.write(self.registers.get_hl(), self.registers.a);
                 self.registers
                     .set_hl(self.registers.get_hl().wrapping_sub(1));
                 (false, 8)
@@ -403,40 +410,28 @@ impl CPU {
                 (false, 8)
             }
             0x34 => {
-                if let Some(value) = memory.get(self.registers.get_hl() as usize) {
-                    let result = value.wrapping_add(1);
-                    self.registers.set_z(result == 0);
-                    self.registers.set_n(false);
-                    self.registers.set_h((*value & 0x0F) == 0x0F);
-                    memory.write_memory(self.registers.get_hl() as usize, result);
-                } else {
-                    eprintln!("Failed to get value at HL {:#06X}", self.registers.get_hl());
-                }
+                let value = memory.read(self.registers.get_hl());
+                let result = value.wrapping_add(1);
+                self.registers.set_z(result == 0);
+                self.registers.set_n(false);
+                self.registers.set_h((value & 0x0F) == 0x0F);
+                memory.write(self.registers.get_hl(), result);
                 (false, 12)
             }
             0x35 => {
-                if let Some(value) = memory.get(self.registers.get_hl() as usize) {
-                    let original = *value;
-                    let result = value.wrapping_sub(1);
-                    memory.write_memory(self.registers.get_hl() as usize, result);
-                    self.registers.set_z(result == 0);
-                    self.registers.set_n(true);
-                    self.registers.set_h((original & 0x0F) == 0x00);
-                } else {
-                    eprintln!(
-                        "Failed to access [HL] at HL {:#06X}",
-                        self.registers.get_hl()
-                    );
-                }
+                let value = memory.read(self.registers.get_hl());
+                let original = value;
+                let result = value.wrapping_sub(1);
+                memory.write(self.registers.get_hl(), result);
+                self.registers.set_z(result == 0);
+                self.registers.set_n(true);
+                self.registers.set_h((original & 0x0F) == 0x00);
                 (false, 12)
             }
             0x36 => {
                 self.registers.pc = self.registers.pc.wrapping_add(1);
-                if let Some(imm8) = memory.get(self.registers.pc as usize) {
-                    memory.write_memory(self.registers.get_hl() as usize, *imm8);
-                } else {
-                    eprintln!("Failed to get imm8 at PC {:#06X}", self.registers.pc);
-                }
+                let imm8 = memory.read(self.registers.pc);
+                memory.write(self.registers.get_hl(), imm8);
                 (false, 12)
             }
             0x37 => {
@@ -459,13 +454,9 @@ impl CPU {
                 (false, 8)
             }
             0x3A => {
-                if let Some(value) = memory.get(self.registers.get_hl() as usize) {
-                    self.registers.a = *value;
-                    self.registers
-                        .set_hl(self.registers.get_hl().wrapping_sub(1));
-                } else {
-                    eprintln!("Failed to get value at HL {:#06X}", self.registers.get_hl());
-                }
+                self.registers.a = memory.read(self.registers.get_hl());
+                self.registers
+                    .set_hl(self.registers.get_hl().wrapping_sub(1));
                 (false, 8)
             }
             0x3B => {
@@ -512,11 +503,7 @@ impl CPU {
                 (false, 4)
             }
             0x46 => {
-                if let Some(value) = memory.get(self.registers.get_hl() as usize) {
-                    self.registers.b = *value;
-                } else {
-                    eprintln!("Failed to get value at HL {:#06X}", self.registers.get_hl());
-                }
+                self.registers.b = memory.read(self.registers.get_hl());
                 (false, 8)
             }
             0x47 => {
@@ -545,11 +532,7 @@ impl CPU {
                 (false, 4)
             }
             0x4E => {
-                if let Some(value) = memory.get(self.registers.get_hl() as usize) {
-                    self.registers.c = *value;
-                } else {
-                    eprintln!("Failed to get value at HL {:#06X}", self.registers.get_hl());
-                }
+                self.registers.c = memory.read(self.registers.get_hl());
                 (false, 8)
             }
             0x4F => {
@@ -578,11 +561,7 @@ impl CPU {
                 (false, 4)
             }
             0x56 => {
-                if let Some(value) = memory.get(self.registers.get_hl() as usize) {
-                    self.registers.d = *value;
-                } else {
-                    eprintln!("Failed to get value at HL {:#06X}", self.registers.get_hl());
-                }
+                self.registers.d = memory.read(self.registers.get_hl());
                 (false, 8)
             }
             0x57 => {
@@ -611,11 +590,7 @@ impl CPU {
                 (false, 4)
             }
             0x5E => {
-                if let Some(value) = memory.get(self.registers.get_hl() as usize) {
-                    self.registers.e = *value;
-                } else {
-                    eprintln!("Failed to get value at HL {:#06X}", self.registers.get_hl());
-                }
+                self.registers.e = memory.read(self.registers.get_hl());
                 (false, 8)
             }
             0x5F => {
@@ -644,11 +619,7 @@ impl CPU {
                 (false, 4)
             }
             0x66 => {
-                if let Some(value) = memory.get(self.registers.get_hl() as usize) {
-                    self.registers.h = *value;
-                } else {
-                    eprintln!("Failed to get value at HL {:#06X}", self.registers.get_hl());
-                }
+                self.registers.h = memory.read(self.registers.get_hl());
                 (false, 8)
             }
             0x67 => {
@@ -677,11 +648,7 @@ impl CPU {
             }
             0x6D => (false, 4),
             0x6E => {
-                if let Some(value) = memory.get(self.registers.get_hl() as usize) {
-                    self.registers.l = *value;
-                } else {
-                    eprintln!("Failed to get value at HL {:#06X}", self.registers.get_hl());
-                }
+                self.registers.l = memory.read(self.registers.get_hl());
                 (false, 8)
             }
             0x6F => {
@@ -689,27 +656,27 @@ impl CPU {
                 (false, 4)
             }
             0x70 => {
-                memory.write_memory(self.registers.get_hl() as usize, self.registers.b);
+                memory.write(self.registers.get_hl(), self.registers.b);
                 (false, 8)
             }
             0x71 => {
-                memory.write_memory(self.registers.get_hl() as usize, self.registers.c);
+                memory.write(self.registers.get_hl(), self.registers.c);
                 (false, 8)
             }
             0x72 => {
-                memory.write_memory(self.registers.get_hl() as usize, self.registers.d);
+                memory.write(self.registers.get_hl(), self.registers.d);
                 (false, 8)
             }
             0x73 => {
-                memory.write_memory(self.registers.get_hl() as usize, self.registers.e);
+                memory.write(self.registers.get_hl(), self.registers.e);
                 (false, 8)
             }
             0x74 => {
-                memory.write_memory(self.registers.get_hl() as usize, self.registers.h);
+                memory.write(self.registers.get_hl(), self.registers.h);
                 (false, 8)
             }
             0x75 => {
-                memory.write_memory(self.registers.get_hl() as usize, self.registers.l);
+                memory.write(self.registers.get_hl(), self.registers.l);
                 (false, 8)
             }
             0x76 => {
@@ -717,7 +684,7 @@ impl CPU {
                 (false, 4)
             }
             0x77 => {
-                memory.write_memory(self.registers.get_hl() as usize, self.registers.a);
+                memory.write(self.registers.get_hl(), self.registers.a);
                 (false, 8)
             }
             0x78 => {
@@ -745,11 +712,7 @@ impl CPU {
                 (false, 4)
             }
             0x7E => {
-                if let Some(value) = memory.get(self.registers.get_hl() as usize) {
-                    self.registers.a = *value;
-                } else {
-                    eprintln!("Failed to get value at HL {:#06X}", self.registers.get_hl());
-                }
+                self.registers.a = memory.read(self.registers.get_hl());
                 (false, 8)
             }
             0x7F => (false, 4),
@@ -778,11 +741,8 @@ impl CPU {
                 (false, 4)
             }
             0x86 => {
-                if let Some(value) = memory.get(self.registers.get_hl() as usize) {
-                    self.add_a_r8(*value);
-                } else {
-                    eprintln!("Failed to get value at HL {:#06X}", self.registers.get_hl());
-                }
+                let value = memory.read(self.registers.get_hl());
+                self.add_a_r8(value);
                 (false, 8)
             }
             0x87 => {
@@ -814,11 +774,8 @@ impl CPU {
                 (false, 4)
             }
             0x8E => {
-                if let Some(value) = memory.get(self.registers.get_hl() as usize) {
-                    self.adc_a_r8(*value);
-                } else {
-                    eprintln!("Failed to get value at HL {:#06X}", self.registers.get_hl());
-                }
+                let value = memory.read(self.registers.get_hl());
+                self.adc_a_r8(value);
                 (false, 8)
             }
             0x8F => {
@@ -850,11 +807,8 @@ impl CPU {
                 (false, 4)
             }
             0x96 => {
-                if let Some(value) = memory.get(self.registers.get_hl() as usize) {
-                    self.sub_a_r8(*value);
-                } else {
-                    eprintln!("Failed to get value at HL {:#06X}", self.registers.get_hl());
-                }
+                let value = memory.read(self.registers.get_hl());
+                self.sub_a_r8(value);
                 (false, 8)
             }
             0x97 => {
@@ -886,11 +840,8 @@ impl CPU {
                 (false, 4)
             }
             0x9E => {
-                if let Some(value) = memory.get(self.registers.get_hl() as usize) {
-                    self.sbc_a_r8(*value);
-                } else {
-                    eprintln!("Failed to get value at HL {:#06X}", self.registers.get_hl());
-                }
+                let value = memory.read(self.registers.get_hl());
+                self.sbc_a_r8(value);
                 (false, 8)
             }
             0x9F => {
@@ -922,11 +873,8 @@ impl CPU {
                 (false, 4)
             }
             0xA6 => {
-                if let Some(value) = memory.get(self.registers.get_hl() as usize) {
-                    self.and_a_r8(*value);
-                } else {
-                    eprintln!("Failed to get value at HL {:#06X}", self.registers.get_hl());
-                }
+                let value = memory.read(self.registers.get_hl());
+                self.and_a_r8(value);
                 (false, 8)
             }
             0xA7 => {
@@ -958,11 +906,8 @@ impl CPU {
                 (false, 4)
             }
             0xAE => {
-                if let Some(value) = memory.get(self.registers.get_hl() as usize) {
-                    self.xor_a_r8(*value);
-                } else {
-                    eprintln!("Failed to get value at HL {:#06X}", self.registers.get_hl());
-                }
+                let value = memory.read(self.registers.get_hl());
+                self.xor_a_r8(value);
                 (false, 8)
             }
             0xAF => {
@@ -994,11 +939,8 @@ impl CPU {
                 (false, 4)
             }
             0xB6 => {
-                if let Some(value) = memory.get(self.registers.get_hl() as usize) {
-                    self.or_a_r8(*value);
-                } else {
-                    eprintln!("Failed to get value at HL {:#06X}", self.registers.get_hl());
-                }
+                let value = memory.read(self.registers.get_hl());
+                self.or_a_r8(value);
                 (false, 8)
             }
             0xB7 => {
@@ -1030,11 +972,8 @@ impl CPU {
                 (false, 4)
             }
             0xBE => {
-                if let Some(value) = memory.get(self.registers.get_hl() as usize) {
-                    self.cp_a_r8(*value);
-                } else {
-                    eprintln!("Failed to get value at HL {:#06X}", self.registers.get_hl());
-                }
+                let value = memory.read(self.registers.get_hl());
+                self.cp_a_r8(value);
                 (false, 8)
             }
             0xBF => {
@@ -1082,11 +1021,8 @@ impl CPU {
             }
             0xC6 => {
                 self.registers.pc = self.registers.pc.wrapping_add(1);
-                if let Some(n8) = memory.get(self.registers.pc as usize) {
-                    self.add_a_r8(*n8);
-                } else {
-                    eprintln!("Failed to get value at PC {:#06X}", self.registers.pc);
-                }
+                let n8 = memory.read(self.registers.pc);
+                self.add_a_r8(n8);
                 (false, 8)
             }
             0xC7 => {
@@ -1117,14 +1053,8 @@ impl CPU {
             0xCB => {
                 self.registers.pc = self.registers.pc.wrapping_add(1);
                 let mut cycles = 4;
-                if let Some(prefix_opcode) = memory.get(self.registers.pc as usize) {
-                    cycles += self.process_prefix(*prefix_opcode, memory);
-                } else {
-                    eprintln!(
-                        "Failed to access prefix_opcode at PC {:#06X}",
-                        self.registers.pc
-                    );
-                }
+                let prefix_opcode = memory.read(self.registers.pc);
+                cycles += self.process_prefix(prefix_opcode, memory);
                 (false, cycles)
             }
             0xCC => {
@@ -1142,11 +1072,8 @@ impl CPU {
             }
             0xCE => {
                 self.registers.pc = self.registers.pc.wrapping_add(1);
-                if let Some(n8) = memory.get(self.registers.pc as usize) {
-                    self.adc_a_r8(*n8);
-                } else {
-                    eprintln!("Failed to get value at PC {:#06X}", self.registers.pc);
-                }
+                let n8 = memory.read(self.registers.pc);
+                self.adc_a_r8(n8);
                 (false, 8)
             }
             0xCF => {
@@ -1196,11 +1123,8 @@ impl CPU {
             }
             0xD6 => {
                 self.registers.pc = self.registers.pc.wrapping_add(1);
-                if let Some(n8) = memory.get(self.registers.pc as usize) {
-                    self.sub_a_r8(*n8);
-                } else {
-                    eprintln!("Failed to get value at PC {:#06X}", self.registers.pc);
-                }
+                let n8 = memory.read(self.registers.pc);
+                self.sub_a_r8(n8);
                 (false, 8)
             }
             0xD7 => {
@@ -1252,11 +1176,8 @@ impl CPU {
             }
             0xDE => {
                 self.registers.pc = self.registers.pc.wrapping_add(1);
-                if let Some(n8) = memory.get(self.registers.pc as usize) {
-                    self.sbc_a_r8(*n8);
-                } else {
-                    eprintln!("Failed to get value at PC {:#06X}", self.registers.pc);
-                }
+                let n8 = memory.read(self.registers.pc);
+                self.sbc_a_r8(n8);
                 (false, 8)
             }
             0xDF => {
@@ -1265,12 +1186,9 @@ impl CPU {
             }
             0xE0 => {
                 self.registers.pc = self.registers.pc.wrapping_add(1);
-                if let Some(value) = memory.get(self.registers.pc as usize) {
-                    let address = 0xFF00 | *value as u16;
-                    memory.write_memory(address as usize, self.registers.a);
-                } else {
-                    eprintln!("Failed to get value at PC {:#06X}", self.registers.pc);
-                }
+                let value = memory.read(self.registers.pc);
+                let address = 0xFF00 | value as u16;
+                memory.write(address, self.registers.a);
                 (false, 12)
             }
             0xE1 => {
@@ -1280,7 +1198,7 @@ impl CPU {
             }
             0xE2 => {
                 let address = 0xFF00 | self.registers.c as u16;
-                memory.write_memory(address as usize, self.registers.a);
+                memory.write(address, self.registers.a);
                 (false, 8)
             }
             0xE3 => {
@@ -1301,11 +1219,8 @@ impl CPU {
             }
             0xE6 => {
                 self.registers.pc = self.registers.pc.wrapping_add(1);
-                if let Some(n8) = memory.get(self.registers.pc as usize) {
-                    self.and_a_r8(*n8);
-                } else {
-                    eprintln!("Failed to get value at PC {:#06X}", self.registers.pc);
-                }
+                let n8 = memory.read(self.registers.pc);
+                self.and_a_r8(n8);
                 (false, 8)
             }
             0xE7 => {
@@ -1314,21 +1229,17 @@ impl CPU {
             }
             0xE8 => {
                 self.registers.pc = self.registers.pc.wrapping_add(1);
-                if let Some(e8) = memory.get(self.registers.pc as usize) {
-                    let offset = *e8 as i8 as i16;
-                    let original_sp = self.registers.sp;
-                    self.registers.sp = original_sp.wrapping_add_signed(offset);
-
-                    self.registers.set_z(false);
-                    self.registers.set_n(false);
-                    let sp_lo = (original_sp & 0xFF) as u8;
-                    let e8_u8 = *e8;
-                    let sum = sp_lo as u16 + e8_u8 as u16;
-                    self.registers.set_h((sp_lo & 0x0F) + (e8_u8 & 0x0F) > 0x0F);
-                    self.registers.set_c(sum > 0xFF);
-                } else {
-                    eprintln!("Failed to get e8 at PC {:#06X}", self.registers.pc);
-                }
+                let e8 = memory.read(self.registers.pc);
+                let offset = e8 as i8 as i16;
+                let original_sp = self.registers.sp;
+                self.registers.sp = original_sp.wrapping_add_signed(offset);
+
+                self.registers.set_z(false);
+                self.registers.set_n(false);
+                let sp_lo = (original_sp & 0xFF) as u8;
+                let sum = sp_lo as u16 + e8 as u16;
+                self.registers.set_h((sp_lo & 0x0F) + (e8 & 0x0F) > 0x0F);
+                self.registers.set_c(sum > 0xFF);
                 (false, 16)
             }
             0xE9 => {
@@ -1337,23 +1248,11 @@ impl CPU {
             }
             0xEA => {
                 self.registers.pc = self.registers.pc.wrapping_add(1);
-                if let Some(low) = memory.get(self.registers.pc as usize) {
-                    self.registers.pc = self.registers.pc.wrapping_add(1);
-                    if let Some(high) = memory.get(self.registers.pc as usize) {
-                        let address = ((*high as u16) << 8) | *low as u16;
-                        memory.write_memory(address as usize, self.registers.a);
-                    } else {
-                        eprintln!(
-                            "Failed to get high value of a16 at PC {:#06X}",
-                            self.registers.pc
-                        );
-                    }
-                } else {
-                    eprintln!(
-                        "Failed to get low value of a16 at PC {:#06X}",
-                        self.registers.pc
-                    );
-                }
+                let low = memory.read(self.registers.pc);
+                self.registers.pc = self.registers.pc.wrapping_add(1);
+                let high = memory.read(self.registers.pc);
+                let address = ((high as u16) << 8) | low as u16;
+                memory.write(address, self.registers.a);
                 (false, 16)
             }
             0xEB => {
@@ -1376,11 +1275,8 @@ impl CPU {
             }
             0xEE => {
                 self.registers.pc = self.registers.pc.wrapping_add(1);
-                if let Some(n8) = memory.get(self.registers.pc as usize) {
-                    self.xor_a_r8(*n8);
-                } else {
-                    eprintln!("Failed to get value at PC {:#06X}", self.registers.pc);
-                }
+                let n8 = memory.read(self.registers.pc);
+                self.xor_a_r8(n8);
                 (false, 8)
             }
             0xEF => {
@@ -1389,16 +1285,9 @@ impl CPU {
             }
             0xF0 => {
                 self.registers.pc = self.registers.pc.wrapping_add(1);
-                if let Some(value) = memory.get(self.registers.pc as usize) {
-                    let address = 0xFF00 | *value as u16;
-                    if let Some(goal_value) = memory.get(address as usize) {
-                        self.registers.a = *goal_value;
-                    } else {
-                        eprintln!("Failed to get value at address = {:#06X}", address);
-                    }
-                } else {
-                    eprintln!("Failed to get value at PC {:#06X}", self.registers.pc);
-                }
+                let value = memory.read(self.registers.pc);
+                let address = 0xFF00 | value as u16;
+                self.registers.a = memory.read(address);
                 (false, 12)
             }
             0xF1 => {
@@ -1409,11 +1298,7 @@ impl CPU {
             }
             0xF2 => {
                 let address = 0xFF00 | self.registers.c as u16;
-                if let Some(value) = memory.get(address as usize) {
-                    self.registers.a = *value;
-                } else {
-                    eprintln!("Failed to get value at address = {:#06X}", address);
-                }
+                self.registers.a = memory.read(address);
                 (false, 8)
             }
             0xF3 => {
@@ -1433,11 +1318,8 @@ impl CPU {
             }
             0xF6 => {
                 self.registers.pc = self.registers.pc.wrapping_add(1);
-                if let Some(n8) = memory.get(self.registers.pc as usize) {
-                    self.or_a_r8(*n8);
-                } else {
-                    eprintln!("Failed to get value at PC {:#06X}", self.registers.pc);
-                }
+                let n8 = memory.read(self.registers.pc);
+                self.or_a_r8(n8);
                 (false, 8)
             }
             0xF7 => {
@@ -1446,19 +1328,15 @@ impl CPU {
             }
             0xF8 => {
                 self.registers.pc = self.registers.pc.wrapping_add(1);
-                if let Some(offset) = memory.get(self.registers.pc as usize) {
-                    let sp = self.registers.sp;
-                    let offset = *offset as i8 as i16 as u16;
-                    let result = sp.wrapping_add(offset);
-
-                    self.registers.set_z(false);
-                    self.registers.set_n(false);
-                    self.registers.set_h(((sp & 0x0F) + (offset & 0x0F)) > 0x0F);
-                    self.registers.set_c(((sp & 0xFF) + (offset & 0xFF)) > 0xFF);
-                    self.registers.set_hl(result);
-                } else {
-                    eprintln!("Failed to get offset at PC = {:#06X}", self.registers.pc);
-                }
+                let sp = self.registers.sp;
+                let offset = memory.read(self.registers.pc) as i8 as i16 as u16;
+                let result = sp.wrapping_add(offset);
+
+                self.registers.set_z(false);
+                self.registers.set_n(false);
+                self.registers.set_h(((sp & 0x0F) + (offset & 0x0F)) > 0x0F);
+                self.registers.set_c(((sp & 0xFF) + (offset & 0xFF)) > 0xFF);
+                self.registers.set_hl(result);
                 (false, 12)
             }
             0xF9 => {
@@ -1467,27 +1345,11 @@ impl CPU {
             }
             0xFA => {
                 self.registers.pc = self.registers.pc.wrapping_add(1);
-                if let Some(low) = memory.get(self.registers.pc as usize) {
-                    self.registers.pc = self.registers.pc.wrapping_add(1);
-                    if let Some(high) = memory.get(self.registers.pc as usize) {
-                        let address = ((*high as u16) << 8) | *low as u16;
-                        if let Some(value_goal) = memory.get(address as usize) {
-                            self.registers.a = *value_goal;
-                        } else {
-                            eprintln!("Failed to get value at address = {:#06X}", address);
-                        }
-                    } else {
-                        eprintln!(
-                            "Failed to get high value of a16 at PC {:#06X}",
-                            self.registers.pc
-                        );
-                    }
-                } else {
-                    eprintln!(
-                        "Failed to get low value of a16 at PC {:#06X}",
-                        self.registers.pc
-                    );
-                }
+                let low = memory.read(self.registers.pc);
+                self.registers.pc = self.registers.pc.wrapping_add(1);
+                let high = memory.read(self.registers.pc);
+                let address = ((high as u16) << 8) | low as u16;
+                self.registers.a = memory.read(address);
                 (false, 16)
             }
             0xFB => {
@@ -1508,11 +1370,8 @@ impl CPU {
             }
             0xFE => {
                 self.registers.pc = self.registers.pc.wrapping_add(1);
-                if let Some(n8) = memory.get(self.registers.pc as usize) {
-                    self.cp_a_r8(*n8);
-                } else {
-                    eprintln!("Failed to get value at PC {:#06X}", self.registers.pc);
-                }
+                let n8 = memory.read(self.registers.pc);
+                self.cp_a_r8(n8);
                 (false, 8)
             }
             0xFF => {
@@ -1600,14 +1459,9 @@ impl CPU {
         self.registers.a = result;
     }
 
-    fn ld_r8_n8(&mut self, memory: &mut Memory) -> u8 {
+    fn ld_r8_n8<B: Bus>(&mut self, memory: &mut B) -> u8 {
         self.registers.pc = self.registers.pc.wrapping_add(1);
-        if let Some(n8) = memory.get(self.registers.pc as usize) {
-            *n8
-        } else {
-            eprintln!("Failed to get imm8 at PC {:#06X}", self.registers.pc);
-            0
-        }
+        memory.read(self.registers.pc)
     }
 
     fn dec_r8(&mut self, reg: u8) -> u8 {
@@ -1646,143 +1500,85 @@ impl CPU {
         self.registers.set_c(sum > 0xFFFF);
     }
 
-    fn ld_r16_n16(&mut self, memory: &mut Memory, opcode: u8) {
+    fn ld_r16_n16<B: Bus>(&mut self, memory: &mut B, opcode: u8) {
         self.registers.pc = self.registers.pc.wrapping_add(1);
-        if let Some(low) = memory.get(self.registers.pc as usize) {
-            self.registers.pc = self.registers.pc.wrapping_add(1);
-            if let Some(high) = memory.get(self.registers.pc as usize) {
-                let immediate = ((*high as u16) << 8) | *low as u16;
-                match (opcode & 0x30) >> 4 {
-                    0 => self.registers.set_bc(immediate),
-                    1 => self.registers.set_de(immediate),
-                    2 => self.registers.set_hl(immediate),
-                    3 => self.registers.sp = immediate,
-                    _ => unreachable!(),
-                }
-            } else {
-                eprintln!(
-                    "Failed to get high value of immediate at PC {:#06X}",
-                    self.registers.pc
-                );
-            }
-        } else {
-            eprintln!(
-                "Failed to get low value of immediate at PC {:#06X}",
-                self.registers.pc
-            );
+        let low = memory.read(self.registers.pc);
+        self.registers.pc = self.registers.pc.wrapping_add(1);
+        let high = memory.read(self.registers.pc);
+        let immediate = ((high as u16) << 8) | low as u16;
+        match (opcode & 0x30) >> 4 {
+            0 => self.registers.set_bc(immediate),
+            1 => self.registers.set_de(immediate),
+            2 => self.registers.set_hl(immediate),
+            3 => self.registers.sp = immediate,
+            _ => unreachable!(),
         }
     }
 
-    fn rst(&mut self, dest: u8, memory: &mut Memory) {
+    fn rst<B: Bus>(&mut self, dest: u8, memory: &mut B) {
         let return_address = self.registers.pc.wrapping_add(1);
         self.registers.sp = self.registers.sp.wrapping_sub(1);
-        memory.write_memory(self.registers.sp as usize, (return_address >> 8) as u8);
+        memory.write(self.registers.sp, (return_address >> 8) as u8);
         self.registers.sp = self.registers.sp.wrapping_sub(1);
-        memory.write_memory(self.registers.sp as usize, return_address as u8);
+        memory.write(self.registers.sp, return_address as u8);
         self.registers.pc = dest as u16;
     }
 
-    fn pop(&mut self, memory: &Memory) -> u16 {
-        let low = memory
-            .get(self.registers.sp as usize)
-            .expect("Failed to get low value of pop");
+    fn pop<B: Bus>(&mut self, memory: &mut B) -> u16 {
+        let low = memory.read(self.registers.sp);
         self.registers.sp = self.registers.sp.wrapping_add(1);
-        let high = memory
-            .get(self.registers.sp as usize)
-            .expect("Failed to get high value of pop");
+        let high = memory.read(self.registers.sp);
         self.registers.sp = self.registers.sp.wrapping_add(1);
-        u16::from_le_bytes([*low, *high])
+        u16::from_le_bytes([low, high])
     }
 
-    fn push(&mut self, r16: u16, memory: &mut Memory) {
+    fn push<B: Bus>(&mut self, r16: u16, memory: &mut B) {
         let low = r16 as u8;
         let high = (r16 >> 8) as u8;
         self.registers.sp = self.registers.sp.wrapping_sub(1);
-        memory.write_memory(self.registers.sp as usize, high);
+        memory.write(self.registers.sp, high);
         self.registers.sp = self.registers.sp.wrapping_sub(1);
-        memory.write_memory(self.registers.sp as usize, low);
+        memory.write(self.registers.sp, low);
     }
 
-    fn call(&mut self, memory: &mut Memory) {
+    fn call<B: Bus>(&mut self, memory: &mut B) {
         self.registers.pc = self.registers.pc.wrapping_add(1);
-        if let Some(low) = memory.get(self.registers.pc as usize) {
-            self.registers.pc = self.registers.pc.wrapping_add(1);
-            if let Some(high) = memory.get(self.registers.pc as usize) {
-                let address = ((*high as u16) << 8) | *low as u16;
-                let return_address = self.registers.pc.wrapping_add(1);
-                self.registers.sp = self.registers.sp.wrapping_sub(1);
-                memory.write_memory(self.registers.sp as usize, (return_address >> 8) as u8);
-                self.registers.sp = self.registers.sp.wrapping_sub(1);
-                memory.write_memory(self.registers.sp as usize, return_address as u8);
-                self.registers.pc = address;
-            } else {
-                eprintln!(
-                    "Failed to get high value of call address at PC {:#06X}",
-                    self.registers.pc
-                );
-            }
-        } else {
-            eprintln!(
-                "Failed to get low value of call address at PC {:#06X}",
-                self.registers.pc
-            );
-        }
+        let low = memory.read(self.registers.pc);
+        self.registers.pc = self.registers.pc.wrapping_add(1);
+        let high = memory.read(self.registers.pc);
+        let address = ((high as u16) << 8) | low as u16;
+        let return_address = self.registers.pc.wrapping_add(1);
+        self.registers.sp = self.registers.sp.wrapping_sub(1);
+        memory.write(self.registers.sp, (return_address >> 8) as u8);
+        self.registers.sp = self.registers.sp.wrapping_sub(1);
+        memory.write(self.registers.sp, return_address as u8);
+        self.registers.pc = address;
     }
 
-    fn ret(&mut self, memory: &mut Memory) {
-        if let Some(low) = memory.get(self.registers.sp as usize) {
-            self.registers.sp = self.registers.sp.wrapping_add(1);
-            if let Some(high) = memory.get(self.registers.sp as usize) {
-                self.registers.sp = self.registers.sp.wrapping_add(1);
-                let return_address = ((*high as u16) << 8) | *low as u16;
-                self.registers.pc = return_address;
-            } else {
-                eprintln!(
-                    "Failed to get high value of return address at PC {:#06X}",
-                    self.registers.pc
-                );
-            }
-        } else {
-            eprintln!(
-                "Failed to get low value of return address at PC {:#06X}",
-                self.registers.pc
-            );
-        }
+    fn ret<B: Bus>(&mut self, memory: &mut B) {
+        let low = memory.read(self.registers.sp);
+        self.registers.sp = self.registers.sp.wrapping_add(1);
+        let high = memory.read(self.registers.sp);
+        self.registers.sp = self.registers.sp.wrapping_add(1);
+        let return_address = ((high as u16) << 8) | low as u16;
+        self.registers.pc = return_address;
     }
 
-    fn jump_absolute(&mut self, memory: &mut Memory) {
+    fn jump_absolute<B: Bus>(&mut self, memory: &mut B) {
         self.registers.pc = self.registers.pc.wrapping_add(1);
-        if let Some(low) = memory.get(self.registers.pc as usize) {
-            self.registers.pc = self.registers.pc.wrapping_add(1);
-            if let Some(high) = memory.get(self.registers.pc as usize) {
-                let address = ((*high as u16) << 8) | *low as u16;
-                self.registers.pc = address;
-            } else {
-                eprintln!(
-                    "Failed to get high value of jump address at PC {:#06X}",
-                    self.registers.pc
-                );
-            }
-        } else {
-            eprintln!(
-                "Failed to get low value of jump address at PC {:#06X}",
-                self.registers.pc
-            );
-        }
+        let low = memory.read(self.registers.pc);
+        self.registers.pc = self.registers.pc.wrapping_add(1);
+        let high = memory.read(self.registers.pc);
+        let address = ((high as u16) << 8) | low as u16;
+        self.registers.pc = address;
     }
 
-    fn jump_relative(&mut self, memory: &mut Memory) {
-        if let Some(offset) = memory.get(self.registers.pc as usize) {
-            self.registers.pc = self.registers.pc.wrapping_add_signed(*offset as i8 as i16);
-        } else {
-            eprintln!(
-                "Failed to get offset for jump at PC {:#06X}",
-                self.registers.pc
-            );
-        }
+    fn jump_relative<B: Bus>(&mut self, memory: &mut B) {
+        let offset = memory.read(self.registers.pc);
+        self.registers.pc = self.registers.pc.wrapping_add_signed(offset as i8 as i16);
     }
 
-    fn process_prefix(&mut self, prefix: u8, memory: &mut Memory) -> u64 {
+    fn process_prefix<B: Bus>(&mut self, prefix: u8, memory: &mut B) -> u64 {
         let operand = prefix & 0x07;
         let bit = (prefix >> 3) & 0x07;
         let group = prefix >> 6;
@@ -1797,7 +1593,7 @@ impl CPU {
         4 + added_cycles
     }
 
-    fn handle_rotate_shift(&mut self, opcode: u8, operand: u8, memory: &mut Memory) -> u64 {
+    fn handle_rotate_shift<B: Bus>(&mut self, opcode: u8, operand: u8, memory: &mut B) -> u64 {
         let (value, cycles) = self.get_operand_value(operand, memory);
         let (result, new_c) = match opcode & 0xF8 {
             0x00 => (value.rotate_left(1), (value >> 7) & 1), // RLC
@@ -1829,7 +1625,7 @@ impl CPU {
         cycles + added_cycles
     }
 
-    fn handle_bit_test(&mut self, bit: u8, operand: u8, memory: &mut Memory) -> u64 {
+    fn handle_bit_test<B: Bus>(&mut self, bit: u8, operand: u8, memory: &mut B) -> u64 {
         let (value, cycles) = self.get_operand_value(operand, memory);
         let mask = 1 << bit;
         self.registers.set_z((value & mask) == 0);
@@ -1838,21 +1634,21 @@ impl CPU {
         cycles
     }
 
-    fn handle_bit_reset(&mut self, bit: u8, operand: u8, memory: &mut Memory) -> u64 {
+    fn handle_bit_reset<B: Bus>(&mut self, bit: u8, operand: u8, memory: &mut B) -> u64 {
         let (value, cycles) = self.get_operand_value(operand, memory);
         let result = value & !(1 << bit);
         let added_cycles = self.set_operand_value(operand, result, memory);
         cycles + added_cycles
     }
 
-    fn handle_bit_set(&mut self, bit: u8, operand: u8, memory: &mut Memory) -> u64 {
+    fn handle_bit_set<B: Bus>(&mut self, bit: u8, operand: u8, memory: &mut B) -> u64 {
         let (value, cycles) = self.get_operand_value(operand, memory);
         let result = value | (1 << bit);
         let added_cycles = self.set_operand_value(operand, result, memory);
         cycles + added_cycles
     }
 
-    fn get_operand_value(&mut self, operand: u8, memory: &mut Memory) -> (u8, u64) {
+    fn get_operand_value<B: Bus>(&mut self, operand: u8, memory: &mut B) -> (u8, u64) {
         match operand {
             0 => (self.registers.b, 0),
             1 => (self.registers.c, 0),
@@ -1860,20 +1656,13 @@ impl CPU {
             3 => (self.registers.e, 0),
             4 => (self.registers.h, 0),
             5 => (self.registers.l, 0),
-            6 => (
-                *memory
-                    .get(self.registers.get_hl() as usize)
-                    .unwrap_or_else(|| {
-                        panic!("Invalid HL address {:#06X}", self.registers.get_hl())
-                    }),
-                4,
-            ),
+            6 => (memory.read(self.registers.get_hl()), 4),
             7 => (self.registers.a, 0),
             _ => unreachable!(),
         }
     }
 
-    fn set_operand_value(&mut self, operand: u8, value: u8, memory: &mut Memory) -> u64 {
+    fn set_operand_value<B: Bus>(&mut self, operand: u8, value: u8, memory: &mut B) -> u64 {
         match operand {
             0 => self.registers.b = value,
             1 => self.registers.c = value,
@@ -1882,7 +1671,7 @@ impl CPU {
             4 => self.registers.h = value,
             5 => self.registers.l = value,
             6 => {
-                memory.write_memory(self.registers.get_hl() as usize, value);
+                memory.write(self.registers.get_hl(), value);
                 return 4;
             }
             7 => self.registers.a = value,
@@ -1891,3 +1680,222 @@ impl CPU {
         0
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::bus::{BusAccess, RecordingBus};
+    use serde_json::Value;
+    use std::fs;
+
+    const SM83_TESTS: &str = "resources/tests/sm83/v1";
+
+    /// Plain 64K of RAM with no cartridge mapping and no I/O side effects, standing in
+    /// for `Memory` so the tests see exactly the bus activity of one opcode.
+    struct FlatRam {
+        memory: Vec<u8>,
+    }
+
+    impl FlatRam {
+        fn new() -> Self {
+            FlatRam {
+                memory: vec![0; 0x10000],
+            }
+        }
+    }
+
+    impl Bus for FlatRam {
+        fn read(&mut self, address: u16) -> u8 {
+            self.memory[address as usize]
+        }
+
+        fn write(&mut self, address: u16, value: u8) {
+            self.memory[address as usize] = value;
+        }
+
+        fn tick(&mut self, _cycles: u64) {}
+    }
+
+    struct CpuState {
+        registers: Registers,
+        ime: bool,
+        ie: Option<u8>,
+        ram: Vec<(u16, u8)>,
+    }
+
+    fn parse_state(state: &Value) -> CpuState {
+        let byte = |name: &str| state[name].as_u64().unwrap_or(0) as u8;
+        let word = |name: &str| state[name].as_u64().unwrap_or(0) as u16;
+
+        let mut registers = Registers::default();
+        registers.a = byte("a");
+        registers.f = byte("f");
+        registers.b = byte("b");
+        registers.c = byte("c");
+        registers.d = byte("d");
+        registers.e = byte("e");
+        registers.h = byte("h");
+        registers.l = byte("l");
+        registers.sp = word("sp");
+        registers.pc = word("pc");
+
+        let ram = state["ram"]
+            .as_array()
+            .map(|entries| {
+                entries
+                    .iter()
+                    .map(|entry| {
+                        (
+                            entry[0].as_u64().unwrap() as u16,
+                            entry[1].as_u64().unwrap() as u8,
+                        )
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        CpuState {
+            registers,
+            ime: byte("ime") != 0,
+            ie: state["ie"].as_u64().map(|ie| ie as u8),
+            ram,
+        }
+    }
+
+    /// Keeps the M-cycles that actually drive the bus; internal cycles are only counted.
+    fn parse_bus_activity(cycles: &Value) -> (usize, Vec<BusAccess>) {
+        let cycles = cycles.as_array().cloned().unwrap_or_default();
+        let accesses = cycles
+            .iter()
+            .filter_map(|cycle| {
+                let address = cycle.get(0)?.as_u64()? as u16;
+                let value = cycle.get(1)?.as_u64()? as u8;
+                let kind = cycle.get(2)?.as_str()?;
+                if kind.contains('w') {
+                    Some(BusAccess::Write(address, value))
+                } else if kind.contains('r') {
+                    Some(BusAccess::Read(address, value))
+                } else {
+                    None
+                }
+            })
+            .collect();
+        (cycles.len(), accesses)
+    }
+
+    fn run_single_step(test: &Value) -> Result<(), String> {
+        let initial = parse_state(&test["initial"]);
+        let expected = parse_state(&test["final"]);
+        let (m_cycles, expected_bus) = parse_bus_activity(&test["cycles"]);
+
+        let mut memory = RecordingBus::new(FlatRam::new());
+        for &(address, value) in &initial.ram {
+            memory.write(address, value);
+        }
+        if let Some(ie) = initial.ie {
+            memory.write(0xFFFF, ie);
+        }
+        memory.clear();
+
+        let mut cpu = CPU::new();
+        cpu.registers = initial.registers;
+        cpu.ime = initial.ime;
+
+        let opcode = memory.read(cpu.registers.pc);
+        let (jumped, cycles) = cpu.process_opcode(opcode, &mut memory);
+        memory.tick(cycles);
+        if !jumped {
+            cpu.registers.pc = cpu.registers.pc.wrapping_add(1);
+        }
+        let bus = memory.accesses().to_vec();
+
+        let mut errors = Vec::new();
+        let registers = [
+            ("a", cpu.registers.a as u16, expected.registers.a as u16),
+            ("f", cpu.registers.f as u16, expected.registers.f as u16),
+            ("b", cpu.registers.b as u16, expected.registers.b as u16),
+            ("c", cpu.registers.c as u16, expected.registers.c as u16),
+            ("d", cpu.registers.d as u16, expected.registers.d as u16),
+            ("e", cpu.registers.e as u16, expected.registers.e as u16),
+            ("h", cpu.registers.h as u16, expected.registers.h as u16),
+            ("l", cpu.registers.l as u16, expected.registers.l as u16),
+            ("sp", cpu.registers.sp, expected.registers.sp),
+            ("pc", cpu.registers.pc, expected.registers.pc),
+        ];
+        for (name, actual, wanted) in registers {
+            if actual != wanted {
+                errors.push(format!("{name} = {actual:#06X}, expected {wanted:#06X}"));
+            }
+        }
+
+        // EI and RETI only arm IME, it becomes visible after the next instruction.
+        let ime = cpu.ime || cpu.ime_pending > 0;
+        if ime != expected.ime {
+            errors.push(format!("ime = {ime}, expected {}", expected.ime));
+        }
+
+        for &(address, wanted) in &expected.ram {
+            let actual = memory.read(address);
+            if actual != wanted {
+                errors.push(format!(
+                    "[{address:#06X}] = {actual:#04X}, expected {wanted:#04X}"
+                ));
+            }
+        }
+
+        if memory.cycles() != m_cycles as u64 * 4 {
+            errors.push(format!(
+                "took {} cycles, expected {}",
+                memory.cycles(),
+                m_cycles * 4
+            ));
+        }
+        if bus != expected_bus {
+            errors.push(format!("bus activity {bus:?}, expected {expected_bus:?}"));
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join(", "))
+        }
+    }
+
+    #[test]
+    fn sm83_single_step() {
+        let mut files: Vec<_> = fs::read_dir(SM83_TESTS)
+            .unwrap_or_else(|e| panic!("Failed to open SM83 tests at {SM83_TESTS}: {e}"))
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .collect();
+        files.sort();
+
+        let mut failures = Vec::new();
+        for file in files {
+            let name = file.file_stem().unwrap().to_string_lossy().to_string();
+            // Waking from STOP depends on the joypad, which these tests don't model
+            if name == "10" {
+                continue;
+            }
+
+            let tests: Value = serde_json::from_str(&fs::read_to_string(&file).unwrap())
+                .unwrap_or_else(|e| panic!("Failed to parse {}: {e}", file.display()));
+            for test in tests.as_array().into_iter().flatten() {
+                if let Err(error) = run_single_step(test) {
+                    failures.push(format!(
+                        "{}: {error}",
+                        test["name"].as_str().unwrap_or(&name)
+                    ));
+                    break;
+                }
+            }
+        }
+
+        assert!(
+            failures.is_empty(),
+            "{} opcodes failed:\n{}",
+            failures.len(),
+            failures.join("\n")
+        );
+    }
+}
@@ -1,108 +1,286 @@
 use crate::components::apu::APU;
+use crate::components::bus::{Bus, RecordingBus};
+use crate::components::cartridge::{self, CameraSource, Cartridge, CartridgeError, CartridgeHeader};
+use crate::components::cheats::Cheat;
 use crate::components::cpu::CPU;
 use crate::components::memory::Memory;
 use crate::components::ppu::PPU;
 use crate::io;
-use crate::utils::hardware_identification::{
-    cartridge_type_decoder, destination_decoder, ram_size_decoder, rom_size_decoder,
-};
-use crate::utils::licensee::{new_licensee_code_decryption, old_licensee_code_decryption};
+use crate::io::serial_device::SerialDevice;
+use crate::io::serialoutput::SerialOutput;
+use crate::io::savestate::{StateError, StateReader, StateWriter};
+
+pub const CLOCK_RATE: u64 = 4_194_304;
+/// 154 lines of 456 cycles.
+pub const CYCLES_PER_FRAME: u64 = 70224;
+
+const STATE_MAGIC: [u8; 4] = *b"GBSS";
+const STATE_VERSION: u8 = 4;
 
 pub struct Gameboy {
     cpu: CPU,
     pub(crate) ppu: PPU,
     pub(crate) apu: APU,
     memory: Memory,
+    header: Option<CartridgeHeader>,
+    rom: Vec<u8>,
+    /// What a Pocket Camera sees and how an MBC7 is tilted, kept across resets.
+    camera: CameraSource,
+    tilt: (f32, f32),
+    rumble: bool,
+    rumble_callback: Option<Box<dyn FnMut(bool) + Send>>,
+    /// Prints every bus access after each instruction, apart from the register trace.
+    trace_bus: bool,
+    /// How long a save state for the inserted cartridge is, worked out when it goes in.
+    state_size: usize,
     pub(crate) cycles: u64
 }
 
 impl Gameboy {
     pub fn new() -> Self {
-        Gameboy {
+        let mut gameboy = Gameboy {
             cpu: CPU::new(),
             ppu: PPU::new(),
             apu: APU::new(),
             memory: Memory::new(),
+            header: None,
+            rom: Vec::new(),
+            camera: CameraSource::default(),
+            tilt: (0.0, 0.0),
+            rumble: false,
+            rumble_callback: None,
+            trace_bus: false,
+            state_size: 0,
             cycles: 0
-        }
+        };
+        gameboy.state_size = gameboy.save_state().len();
+        gameboy
     }
 
-    pub fn cartridge_to_rom(&mut self, filename: String) {
+    pub fn cartridge_to_rom(&mut self, filename: String) -> Result<(), CartridgeError> {
         println!("Loading ROM: {filename}");
         let cartridge_data = io::cartridge_reader::read_cartridge(filename);
 
-        self.memory
-            .select_mbc(*cartridge_data.get(0x0147).unwrap_or(&0));
+        let header = CartridgeHeader::parse(&cartridge_data)?;
+        // Trimmed dumps and homebrew with an unpatched header still boot
+        if let Err(error) = header.validate(&cartridge_data) {
+            eprintln!("{error}");
+        }
+        println!("{header}");
 
-        self.memory.write_cartridge(&cartridge_data);
+        let cartridge = cartridge::from_rom(cartridge_data.clone())?;
 
-        let title_bytes: Vec<u8> = (0x0134..=0x0143)
-            .filter_map(|addr| cartridge_data.get(addr).copied())
-            .collect();
-        if let Ok(title) = String::from_utf8(title_bytes) {
-            println!("Game Title: {}", title);
-        } else {
-            println!("Failed to read the game title.");
+        if !header.global_checksum_valid() {
+            eprintln!(
+                "Global checksum is {:#06X} but should be {:#06X}",
+                header.global_checksum, header.computed_global_checksum
+            );
         }
 
-        let manufacturer_bytes: Vec<u8> = (0x013F..=0x0142)
-            .filter_map(|addr| cartridge_data.get(addr).copied())
-            .collect();
-        if let Ok(code) = String::from_utf8(manufacturer_bytes) {
-            println!("Manufacturer Code: {}", code);
-        } else {
-            println!("Failed to read manufacturer code.");
+        self.header = Some(header);
+        self.rom = cartridge_data;
+        self.power_on(cartridge);
+        self.memory.cheats.clear();
+        Ok(())
+    }
+
+    /// Pressing the reset combination: the machine starts over from the boot ROM but the
+    /// cartridge keeps everything in its RAM.
+    pub fn reset(&mut self) {
+        let battery = self.memory.cartridge().save_battery();
+        self.reinsert_cartridge(Some(&battery));
+    }
+
+    /// Switching the power off and on: only what the battery backs up survives.
+    pub fn power_cycle(&mut self) {
+        let battery = self.battery();
+        self.reinsert_cartridge(battery.as_deref());
+    }
+
+    /// The contents of battery-backed RAM, or `None` if the cartridge has no battery.
+    pub fn battery(&self) -> Option<Vec<u8>> {
+        self.header
+            .as_ref()
+            .filter(|header| header.has_battery())
+            .map(|_| self.memory.cartridge().save_battery())
+    }
+
+    pub fn load_battery(&mut self, data: &[u8]) {
+        self.memory.cartridge_mut().load_battery(data);
+    }
+
+    fn reinsert_cartridge(&mut self, battery: Option<&[u8]>) {
+        if self.header.is_none() {
+            *self = Gameboy::new();
+            return;
         }
 
-        let cartridge_type = cartridge_data.get(0x0147).unwrap_or(&0);
-        println!(
-            "Hardware present: {}",
-            cartridge_type_decoder(*cartridge_type)
-        );
+        let mut cartridge =
+            cartridge::from_rom(self.rom.clone()).expect("ROM was accepted when it was loaded");
+        if let Some(battery) = battery {
+            cartridge.load_battery(battery);
+        }
+        self.power_on(cartridge);
+    }
 
-        let rom_size = cartridge_data.get(0x0148).unwrap_or(&0);
-        println!("Rom size: {}", rom_size_decoder(*rom_size));
+    fn power_on(&mut self, mut cartridge: Box<dyn Cartridge>) {
+        cartridge.set_camera_source(self.camera.clone());
+        cartridge.set_tilt(self.tilt.0, self.tilt.1);
+        self.cpu = CPU::new();
+        self.ppu = PPU::new();
+        self.apu = APU::new();
+        // Cheats are plugged in between console and cartridge and survive a reset
+        let cheats = std::mem::take(&mut self.memory.cheats);
+        let cheats_enabled = self.memory.cheats_enabled;
+        // ...and so does whatever is plugged into the link port
+        let serial_device = self.disconnect_serial();
+        self.memory = Memory::new();
+        self.memory.insert_cartridge(cartridge);
+        self.memory.cheats = cheats;
+        self.memory.cheats_enabled = cheats_enabled;
+        self.memory.serial_device = serial_device;
+        self.cycles = 0;
+        self.state_size = self.save_state().len();
+        self.update_rumble();
+
+        if self.header.as_ref().is_some_and(|header| header.header_checksum != 0x00) {
+            self.cpu.registers.set_h(true);
+            self.cpu.registers.set_c(true);
+        }
+    }
 
-        let ram_size = cartridge_data.get(0x0149).unwrap_or(&0);
-        println!("Ram size: {}", ram_size_decoder(*ram_size));
+    pub fn header(&self) -> Option<&CartridgeHeader> {
+        self.header.as_ref()
+    }
 
-        let destination_code = cartridge_data.get(0x014A).unwrap_or(&0);
-        println!("Destination: {}", destination_decoder(*destination_code));
+    /// Reads memory the way the CPU sees it right now, without side effects.
+    pub fn peek(&self, address: u16) -> u8 {
+        self.memory.get(address as usize).unwrap_or(0xFF)
+    }
 
-        let old_licensee_code = cartridge_data.get(0x014B).unwrap_or(&0);
-        if *old_licensee_code != 0x33 {
-            println!(
-                "Licensee: {}",
-                old_licensee_code_decryption(*old_licensee_code)
-            );
-        } else {
-            let new_licensee_bytes: Vec<u8> = (0x0144..=0x0145)
-                .filter_map(|addr| cartridge_data.get(addr).copied())
-                .collect();
-            if let Ok(new_licensee) = String::from_utf8(new_licensee_bytes) {
-                println!("Licensee: {}", new_licensee_code_decryption(new_licensee));
-            } else {
-                println!("Failed to read licensee.");
+    /// Writes memory the way the CPU would.
+    pub fn poke(&mut self, address: u16, value: u8) {
+        self.memory.write_memory(address as usize, value);
+    }
+
+    pub fn add_cheat(&mut self, cheat: Cheat) {
+        self.memory.cheats.push(cheat);
+    }
+
+    pub fn cheats(&self) -> &[Cheat] {
+        &self.memory.cheats
+    }
+
+    pub fn cheats_enabled(&self) -> bool {
+        self.memory.cheats_enabled
+    }
+
+    /// Replaces every cheat, for playing back a movie the way it was recorded.
+    pub fn set_cheats(&mut self, cheats: Vec<Cheat>, enabled: bool) {
+        self.memory.cheats = cheats;
+        self.memory.cheats_enabled = enabled;
+    }
+
+    /// Switches all cheats on or off without forgetting them. Returns the new setting.
+    pub fn toggle_cheats(&mut self) -> bool {
+        self.memory.cheats_enabled = !self.memory.cheats_enabled;
+        self.memory.cheats_enabled
+    }
+
+    /// Feeds a Pocket Camera cartridge these pictures instead of the built-in gradient.
+    pub fn set_camera_source(&mut self, source: CameraSource) {
+        self.memory.cartridge_mut().set_camera_source(source.clone());
+        self.camera = source;
+    }
+
+    /// Tilts an MBC7 cartridge, in g with right and down positive.
+    pub fn set_tilt(&mut self, x: f32, y: f32) {
+        self.memory.cartridge_mut().set_tilt(x, y);
+        self.tilt = (x, y);
+    }
+
+    /// Calls `callback` whenever a rumble cartridge switches its motor on or off.
+    pub fn set_rumble_callback(&mut self, callback: impl FnMut(bool) + Send + 'static) {
+        self.rumble_callback = Some(Box::new(callback));
+    }
+
+    fn update_rumble(&mut self) {
+        let rumble = self.memory.cartridge().rumble();
+        if rumble != self.rumble {
+            self.rumble = rumble;
+            if let Some(callback) = &mut self.rumble_callback {
+                callback(rumble);
             }
         }
+    }
+
+    /// Plugs `device` into the link port in place of the serial logger.
+    pub fn connect_serial(&mut self, device: Box<dyn SerialDevice>) {
+        self.memory.serial_device = device;
+    }
+
+    /// Unplugs the current device and puts the serial logger back.
+    pub fn disconnect_serial(&mut self) -> Box<dyn SerialDevice> {
+        std::mem::replace(&mut self.memory.serial_device, Box::new(SerialOutput::new()))
+    }
+
+    /// The last frame drawn, as RGBA.
+    pub fn framebuffer(&self) -> &[u8] {
+        self.ppu.framebuffer()
+    }
+
+    /// SHA-1 of the whole ROM as lowercase hex, the usual way to identify a dump.
+    pub fn rom_sha1(&self) -> String {
+        sha1_smol::Sha1::from(&self.rom).digest().to_string()
+    }
+
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut state = StateWriter::new();
+        for byte in STATE_MAGIC {
+            state.write_u8(byte);
+        }
+        state.write_u8(STATE_VERSION);
+        self.cpu.save_state(&mut state);
+        self.ppu.save_state(&mut state);
+        self.apu.save_state(&mut state);
+        self.memory.save_state(&mut state);
+        state.write_u64(self.cycles);
+        state.into_bytes()
+    }
 
-        let version_number = cartridge_data.get(0x014C).unwrap_or(&0);
-        println!("Version number: {}", *version_number);
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+        // A state for this machine always has the same size, so checking it up front
+        // keeps a foreign state from being half applied.
+        if data.len() != self.state_size {
+            return Err(StateError::Mismatch("size"));
+        }
 
-        if let Some(header_checksum) = cartridge_data.get(0x014D) {
-            if *header_checksum != 0x00 {
-                self.cpu.registers.set_h(true);
-                self.cpu.registers.set_c(true);
+        let mut state = StateReader::new(data);
+        for byte in STATE_MAGIC {
+            if state.read_u8()? != byte {
+                return Err(StateError::Mismatch("not a save state"));
             }
-        } else {
-            eprintln!("Unable to access header checksum at 0x014D");
         }
+        if state.read_u8()? != STATE_VERSION {
+            return Err(StateError::Mismatch("version"));
+        }
+        self.cpu.load_state(&mut state)?;
+        self.ppu.load_state(&mut state)?;
+        self.apu.load_state(&mut state)?;
+        self.memory.load_state(&mut state)?;
+        self.cycles = state.read_u64()?;
+        state.finish()
     }
 
     pub fn toggle_debug_registers(&mut self) {
         self.cpu.toggle_debug_registers();
     }
 
+    pub fn toggle_bus_trace(&mut self) -> bool {
+        self.trace_bus = !self.trace_bus;
+        self.trace_bus
+    }
+
     fn start(&mut self, test: Option<u64>) {
         if let Some(iterations) = test {
             for _i in 0..iterations {
@@ -115,14 +293,38 @@ impl Gameboy {
         }
     }
 
+    /// Runs until a frame's worth of cycles has passed. An instruction that ends past the
+    /// frame boundary is counted against the next frame, so every frame averages out to
+    /// exactly `CYCLES_PER_FRAME`.
+    pub fn run_frame(&mut self) {
+        while self.cycles < CYCLES_PER_FRAME {
+            self.execute_cycle();
+        }
+        self.end_frame();
+    }
+
+    pub(crate) fn end_frame(&mut self) {
+        self.cycles -= CYCLES_PER_FRAME;
+        self.memory.apply_game_shark();
+    }
+
     pub(crate) fn execute_cycle(&mut self) {
+        if self.cpu.stopped {
+            // Everything but the joypad is frozen; cycles still pass so frames keep coming
+            self.cycles += 4;
+            if self.memory.joypad_active() {
+                self.cpu.stopped = false;
+            }
+            return;
+        }
+
         if self.cpu.halted {
             self.ppu.step(4, &mut self.memory);
-            self.memory.update_timer(4);
+            self.memory.tick(4);
             self.cycles += 4;
 
-            let ie = self.memory.get(0xFFFF).copied().unwrap_or(0);
-            let if_ = self.memory.get(0xFF0F).copied().unwrap_or(0);
+            let ie = self.memory.get(0xFFFF).unwrap_or(0);
+            let if_ = self.memory.get(0xFF0F).unwrap_or(0);
             let pending = ie & if_;
             if pending != 0 {
                 self.cpu.halted = false;
@@ -131,9 +333,21 @@ impl Gameboy {
             return;
         }
 
-        if let Some(opcode) = self.memory.get(self.cpu.registers.pc as usize).copied() {
-            let (jumped, cycles) = self.cpu.process_opcode(opcode, &mut self.memory);
-            self.memory.update_timer(cycles);
+        if let Some(opcode) = self.memory.get(self.cpu.registers.pc as usize) {
+            if self.cpu.is_debugging() {
+                self.cpu.print_registers(&mut self.memory);
+            }
+            let (jumped, cycles) = if self.trace_bus {
+                let mut bus = RecordingBus::new(&mut self.memory);
+                let result = self.cpu.process_opcode(opcode, &mut bus);
+                for access in bus.accesses() {
+                    println!("    {access:?}");
+                }
+                result
+            } else {
+                self.cpu.process_opcode(opcode, &mut self.memory)
+            };
+            self.memory.tick(cycles);
             self.cpu.update_ime();
 
             if !jumped {
@@ -148,6 +362,7 @@ impl Gameboy {
             /*End APU Area*/
 
             self.cycles += cycles;
+            self.update_rumble();
             if self.cpu.registers.pc == 0x0100 {
                 self.memory.disable_rom();
             }
@@ -157,7 +372,7 @@ impl Gameboy {
     }
 
     pub(crate) fn write_inputs(&mut self, inputs: u8) {
-        self.memory.input_buffer = inputs;
+        self.memory.set_inputs(inputs);
     }
 }
 
@@ -165,12 +380,83 @@ impl Gameboy {
 mod tests {
     use super::*;
 
+    #[test]
+    fn save_state_round_trips() {
+        let mut gameboy = Gameboy::new();
+        gameboy.start(Some(10_000));
+        let state = gameboy.save_state();
+        gameboy.start(Some(50_000));
+        let expected = gameboy.save_state();
+
+        gameboy.load_state(&state).unwrap();
+        assert_eq!(gameboy.save_state(), state);
+        gameboy.start(Some(50_000));
+        assert_eq!(gameboy.save_state(), expected);
+
+        assert!(gameboy.load_state(&state[..state.len() - 1]).is_err());
+        assert_eq!(gameboy.save_state(), expected);
+    }
+
+    #[test]
+    fn only_truncated_roms_are_refused() {
+        let path =
+            std::env::temp_dir().join(format!("gameboy-header-{}.gb", std::process::id()));
+        let filename = path.to_string_lossy().into_owned();
+
+        // Declares 32 KiB with a header checksum of zero, but is half that
+        std::fs::write(&path, vec![0; 0x4000]).unwrap();
+        let mut gameboy = Gameboy::new();
+        assert!(gameboy.cartridge_to_rom(filename.clone()).is_ok());
+
+        std::fs::write(&path, vec![0; 0x100]).unwrap();
+        assert!(matches!(
+            gameboy.cartridge_to_rom(filename),
+            Err(CartridgeError::Truncated(0x100))
+        ));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn joypad_reflects_inputs_and_interrupts() {
+        let mut gameboy = Gameboy::new();
+        gameboy.memory.write_memory(0xFF00, 0x20);
+        gameboy.memory.write_memory(0xFF0F, 0x00);
+        assert_eq!(gameboy.memory.get(0xFF00), Some(0xEF));
+
+        gameboy.write_inputs(0b1111_1110);
+        assert_eq!(gameboy.memory.get(0xFF00), Some(0xEE));
+        assert_eq!(gameboy.memory.get(0xFF0F), Some(0x10));
+
+        // A is not in the selected row until P1 switches to the buttons
+        gameboy.memory.write_memory(0xFF0F, 0x00);
+        gameboy.write_inputs(0b1110_1111);
+        assert_eq!(gameboy.memory.get(0xFF00), Some(0xEF));
+        assert_eq!(gameboy.memory.get(0xFF0F), Some(0x00));
+        gameboy.memory.write_memory(0xFF00, 0x10);
+        assert_eq!(gameboy.memory.get(0xFF00), Some(0xDE));
+        assert_eq!(gameboy.memory.get(0xFF0F), Some(0x10));
+    }
+
+    #[test]
+    fn joypad_wakes_from_stop() {
+        let mut gameboy = Gameboy::new();
+        gameboy.memory.write_memory(0xFF00, 0x10);
+        gameboy.cpu.stopped = true;
+        gameboy.execute_cycle();
+        assert!(gameboy.cpu.stopped);
+
+        gameboy.write_inputs(0b0111_1111);
+        gameboy.execute_cycle();
+        assert!(!gameboy.cpu.stopped);
+    }
+
     #[test]
     fn rom_01_special() {
         let mut gameboy = Gameboy::new();
         gameboy.cartridge_to_rom(String::from(
             "resources/roms/blargg/cpu_instrs/individual/01-special.gb",
-        ));
+        ))
+        .unwrap();
         gameboy.start(Some(4_000_000));
         let output = gameboy.memory.get_serial_output().get_output();
         assert!(output.contains("Passed"), "Test failed. Output: {}", output);
@@ -181,7 +467,8 @@ mod tests {
         let mut gameboy = Gameboy::new();
         gameboy.cartridge_to_rom(String::from(
             "resources/roms/blargg/cpu_instrs/individual/02-interrupts.gb",
-        ));
+        ))
+        .unwrap();
         gameboy.start(Some(4_000_000));
         let output = gameboy.memory.get_serial_output().get_output();
         assert!(output.contains("Passed"), "Test failed. Output: {}", output);
@@ -192,7 +479,8 @@ mod tests {
         let mut gameboy = Gameboy::new();
         gameboy.cartridge_to_rom(String::from(
             "resources/roms/blargg/cpu_instrs/individual/03-op sp,hl.gb",
-        ));
+        ))
+        .unwrap();
         gameboy.start(Some(4_000_000));
         let output = gameboy.memory.get_serial_output().get_output();
         assert!(output.contains("Passed"), "Test failed. Output: {}", output);
@@ -203,7 +491,8 @@ mod tests {
         let mut gameboy = Gameboy::new();
         gameboy.cartridge_to_rom(String::from(
             "resources/roms/blargg/cpu_instrs/individual/04-op r,imm.gb",
-        ));
+        ))
+        .unwrap();
         gameboy.start(Some(4_000_000));
         let output = gameboy.memory.get_serial_output().get_output();
         assert!(output.contains("Passed"), "Test failed. Output: {}", output);
@@ -214,7 +503,8 @@ mod tests {
         let mut gameboy = Gameboy::new();
         gameboy.cartridge_to_rom(String::from(
             "resources/roms/blargg/cpu_instrs/individual/05-op rp.gb",
-        ));
+        ))
+        .unwrap();
         gameboy.start(Some(4_000_000));
         let output = gameboy.memory.get_serial_output().get_output();
         assert!(output.contains("Passed"), "Test failed. Output: {}", output);
@@ -225,7 +515,8 @@ mod tests {
         let mut gameboy = Gameboy::new();
         gameboy.cartridge_to_rom(String::from(
             "resources/roms/blargg/cpu_instrs/individual/06-ld r,r.gb",
-        ));
+        ))
+        .unwrap();
         gameboy.start(Some(4_000_000));
         let output = gameboy.memory.get_serial_output().get_output();
         assert!(output.contains("Passed"), "Test failed. Output: {}", output);
@@ -236,7 +527,8 @@ mod tests {
         let mut gameboy = Gameboy::new();
         gameboy.cartridge_to_rom(String::from(
             "resources/roms/blargg/cpu_instrs/individual/07-jr,jp,call,ret,rst.gb",
-        ));
+        ))
+        .unwrap();
         gameboy.start(Some(4_000_000));
         let output = gameboy.memory.get_serial_output().get_output();
         assert!(output.contains("Passed"), "Test failed. Output: {}", output);
@@ -247,7 +539,8 @@ mod tests {
         let mut gameboy = Gameboy::new();
         gameboy.cartridge_to_rom(String::from(
             "resources/roms/blargg/cpu_instrs/individual/08-misc instrs.gb",
-        ));
+        ))
+        .unwrap();
         gameboy.start(Some(4_000_000));
         let output = gameboy.memory.get_serial_output().get_output();
         assert!(output.contains("Passed"), "Test failed. Output: {}", output);
@@ -258,7 +551,8 @@ mod tests {
         let mut gameboy = Gameboy::new();
         gameboy.cartridge_to_rom(String::from(
             "resources/roms/blargg/cpu_instrs/individual/09-op r,r.gb",
-        ));
+        ))
+        .unwrap();
         gameboy.start(Some(7_500_000));
         let output = gameboy.memory.get_serial_output().get_output();
         assert!(output.contains("Passed"), "Test failed. Output: {}", output);
@@ -269,7 +563,8 @@ mod tests {
         let mut gameboy = Gameboy::new();
         gameboy.cartridge_to_rom(String::from(
             "resources/roms/blargg/cpu_instrs/individual/10-bit ops.gb",
-        ));
+        ))
+        .unwrap();
         gameboy.start(Some(9_000_000));
         let output = gameboy.memory.get_serial_output().get_output();
         assert!(output.contains("Passed"), "Test failed. Output: {}", output);
@@ -280,18 +575,36 @@ mod tests {
         let mut gameboy = Gameboy::new();
         gameboy.cartridge_to_rom(String::from(
             "resources/roms/blargg/cpu_instrs/individual/11-op a,(hl).gb",
-        ));
+        ))
+        .unwrap();
         gameboy.start(Some(10_500_000));
         let output = gameboy.memory.get_serial_output().get_output();
         assert!(output.contains("Passed"), "Test failed. Output: {}", output);
     }
 
+    #[test]
+    fn rom_mbc1_multicart() {
+        let mut gameboy = Gameboy::new();
+        gameboy.cartridge_to_rom(String::from(
+            "resources/roms/mooneye/emulator-only/mbc1/multicart_rom_8Mb.gb",
+        ))
+        .unwrap();
+        gameboy.start(Some(2_000_000));
+        // Mooneye tests leave the Fibonacci numbers in the registers when they pass
+        let registers = &gameboy.cpu.registers;
+        assert_eq!(
+            [registers.get_bc(), registers.get_de(), registers.get_hl()],
+            [0x0305, 0x080D, 0x1522]
+        );
+    }
+
     #[test]
     fn rom_instr_timing() {
         let mut gameboy = Gameboy::new();
         gameboy.cartridge_to_rom(String::from(
             "resources/roms/blargg/instr_timing/instr_timing.gb",
-        ));
+        ))
+        .unwrap();
         gameboy.start(Some(3_300_000));
         let output = gameboy.memory.get_serial_output().get_output();
         assert!(output.contains("Passed"), "Test failed. Output: {}", output);
@@ -0,0 +1,145 @@
+use crate::components::gameboy::{CYCLES_PER_FRAME, Gameboy};
+use crate::io::serial_device::SerialDevice;
+use std::sync::{Arc, Mutex};
+
+/// Both ends of a virtual link cable.
+#[derive(Default)]
+struct Wire {
+    /// What each side last had in SB.
+    serial_data: [u8; 2],
+    /// A byte the other side clocked into this one, not yet picked up.
+    clocked_in: [Option<u8>; 2],
+}
+
+/// One plug of a cable between two machines in the same process.
+struct CableEnd {
+    wire: Arc<Mutex<Wire>>,
+    side: usize,
+}
+
+impl CableEnd {
+    fn pair() -> [CableEnd; 2] {
+        let wire = Arc::new(Mutex::new(Wire::default()));
+        [0, 1].map(|side| CableEnd {
+            wire: Arc::clone(&wire),
+            side,
+        })
+    }
+}
+
+impl SerialDevice for CableEnd {
+    fn transfer(&mut self, outgoing: u8) -> Option<u8> {
+        let mut wire = self.wire.lock().unwrap();
+        wire.clocked_in[1 - self.side] = Some(outgoing);
+        Some(wire.serial_data[1 - self.side])
+    }
+
+    fn poll(&mut self, outgoing: u8) -> Option<u8> {
+        let mut wire = self.wire.lock().unwrap();
+        wire.serial_data[self.side] = outgoing;
+        wire.clocked_in[self.side].take()
+    }
+}
+
+/// Two Game Boys with a link cable between them, run in cycle lockstep so that
+/// multiplayer sessions play out the same way every time.
+pub struct LinkedPair {
+    gameboys: [Gameboy; 2],
+}
+
+impl LinkedPair {
+    pub fn new(mut first: Gameboy, mut second: Gameboy) -> Self {
+        let [first_end, second_end] = CableEnd::pair();
+        first.connect_serial(Box::new(first_end));
+        second.connect_serial(Box::new(second_end));
+        LinkedPair {
+            gameboys: [first, second],
+        }
+    }
+
+    pub fn gameboys_mut(&mut self) -> &mut [Gameboy; 2] {
+        &mut self.gameboys
+    }
+
+    pub fn write_inputs(&mut self, inputs: [u8; 2]) {
+        for (gameboy, inputs) in self.gameboys.iter_mut().zip(inputs) {
+            gameboy.write_inputs(inputs);
+        }
+    }
+
+    /// Runs both machines for a frame, always stepping the one that is behind.
+    pub fn run_frame(&mut self) {
+        let [first, second] = &mut self.gameboys;
+        while first.cycles < CYCLES_PER_FRAME || second.cycles < CYCLES_PER_FRAME {
+            if first.cycles <= second.cycles {
+                first.execute_cycle();
+            } else {
+                second.execute_cycle();
+            }
+        }
+        first.end_frame();
+        second.end_frame();
+    }
+
+    pub fn framebuffers(&self) -> [&[u8]; 2] {
+        [
+            self.gameboys[0].framebuffer(),
+            self.gameboys[1].framebuffer(),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::bus::Bus;
+    use crate::components::memory::Memory;
+
+    fn linked_memories() -> [Memory; 2] {
+        let mut memories = [Memory::new(), Memory::new()];
+        for (memory, end) in memories.iter_mut().zip(CableEnd::pair()) {
+            memory.serial_device = Box::new(end);
+            memory.write_memory(0xFF0F, 0x00);
+        }
+        memories
+    }
+
+    #[test]
+    fn cable_swaps_serial_data() {
+        let mut memories = linked_memories();
+        let [first, second] = &mut memories;
+        first.write_memory(0xFF01, 0x12);
+        second.write_memory(0xFF01, 0x34);
+        second.write_memory(0xFF02, 0x80);
+        first.write_memory(0xFF02, 0x81);
+
+        for _ in 0..4096 / 4 {
+            first.tick(4);
+            second.tick(4);
+        }
+        assert_eq!(first.get(0xFF01), Some(0x34));
+        assert_eq!(second.get(0xFF01), Some(0x12));
+        for memory in [first, second] {
+            assert_eq!(memory.get(0xFF02).unwrap() & 0x80, 0x00);
+            assert_eq!(memory.get(0xFF0F), Some(0x08));
+        }
+    }
+
+    #[test]
+    fn byte_clocked_in_early_waits_for_sc() {
+        let mut memories = linked_memories();
+        let [first, second] = &mut memories;
+        first.write_memory(0xFF01, 0x12);
+        first.write_memory(0xFF02, 0x81);
+        for _ in 0..4096 / 4 {
+            first.tick(4);
+            second.tick(4);
+        }
+        assert_eq!(second.get(0xFF0F), Some(0x00));
+
+        second.write_memory(0xFF02, 0x80);
+        second.tick(4);
+        assert_eq!(second.get(0xFF01), Some(0x12));
+        assert_eq!(second.get(0xFF0F), Some(0x08));
+    }
+}
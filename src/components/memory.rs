@@ -1,58 +1,49 @@
-use crate::components::memory::Mbc::{MBC0, MBC1, MBC2, MBC3, MBC5, MBC6, MBC7, MMM01};
+use crate::components::bus::Bus;
+use crate::components::cartridge::{Cartridge, NoMbc, RAM_BANK_SIZE};
+use crate::components::cheats::Cheat;
 use crate::io::cartridge_reader::read_cartridge;
+use crate::io::savestate::{StateError, StateReader, StateWriter};
+use crate::io::serial_device::SerialDevice;
 use crate::io::serialoutput::SerialOutput;
+use std::any::Any;
+
+/// Shifting out 8 bits with the internal 8192 Hz clock.
+const SERIAL_TRANSFER_CYCLES: u64 = 4096;
 
 pub struct Memory {
     memory: [u8; 0x10000],
-    rom: Vec<u8>,
-    ram: Vec<u8>,
-    rombank: usize,
-    rombanks: usize,
-    rambank: usize,
-    rambanks: usize,
-    ram_enabled: bool,
-    banking_mode: u8,
-    start_cartridge: [u8; 0x100],
-    serial_output: SerialOutput,
+    cartridge: Box<dyn Cartridge>,
+    boot_rom: [u8; 0x100],
+    boot_rom_enabled: bool,
+    pub(crate) serial_device: Box<dyn SerialDevice>,
+    serial_cycles: u64,
+    /// A transfer went out and the device hands its byte over through `poll`.
+    serial_reply_pending: bool,
     cycles_div: u64,
     cycles_tima: u64,
-    mbc: Mbc,
-    pub(crate) input_buffer: u8,
-}
-
-#[derive(PartialEq)]
-enum Mbc {
-    MBC0,
-    MBC1,
-    MBC2,
-    MBC3,
-    MBC5,
-    MBC6,
-    MBC7,
-    MMM01,
+    input_buffer: u8,
+    pub(crate) cheats: Vec<Cheat>,
+    pub(crate) cheats_enabled: bool,
 }
 
 impl Memory {
     pub fn new() -> Self {
         let mut mem = Memory {
             memory: [0xFF; 0x10000],
-            rom: Vec::new(),
-            ram: Vec::new(),
-            rombank: 1,
-            rombanks: 1,
-            rambank: 0,
-            rambanks: 0,
-            ram_enabled: false,
-            banking_mode: 0,
-            start_cartridge: [0; 0x100],
-            serial_output: SerialOutput::new(),
+            cartridge: Box::new(NoMbc::new(Vec::new(), 0)),
+            boot_rom: [0; 0x100],
+            boot_rom_enabled: false,
+            serial_device: Box::new(SerialOutput::new()),
+            serial_cycles: 0,
+            serial_reply_pending: false,
             cycles_div: 0,
             cycles_tima: 0,
-            mbc: MBC0,
             input_buffer: 0xFF,
+            cheats: Vec::new(),
+            cheats_enabled: true,
         };
 
-        mem.memory[0xFF00] = 0xCF; //P1
+        mem.memory[0xFF00] = 0x00; //P1, only the select bits are stored
         mem.memory[0xFF01] = 0x00; //SB
         mem.memory[0xFF02] = 0x7E; //SC
         mem.memory[0xFF04] = 0xAB; //DIV
@@ -98,153 +89,28 @@ impl Memory {
         mem
     }
 
-    pub fn get(&self, index: usize) -> Option<&u8> {
-        if self.mbc == MBC0 {
-            self.memory.get(index)
-        } else if self.mbc == MBC1 {
-            match index{
-                0x0000..0x8000 => {
-                    let bank = if index < 0x4000 {
-                        if self.banking_mode == 0 {
-                            0
-                        }
-                        else {
-                            self.rombank & 0xE0
-                        }
-                    } else {
-                        self.rombank
-                    };
-                    let idx = (bank * 0x4000) | (index & 0x3FFF);
-                    self.rom.get(idx)
-                }
-                0xA000..0xC000 => {
-                    if !self.ram_enabled {return Some(&0xFFu8)};
-                    let bank = if self.banking_mode == 1 {self.rambank} else {0};
-                    let idx = (bank * 0x2000) | (index & 0x1FFF);
-                    self.ram.get(idx)
-                }
-                _ => self.memory.get(index)
-            }
-        } else if self.mbc == MBC5 {
-            match index {
-                0x0000..0x8000 => {
-                    let bank = if index < 0x4000 {
-                        0
-                    } else {
-                        self.rombank & 0x1FF
-                    };
-                    let idx = (bank * 0x4000) | (index & 0x3FFF);
-                    self.rom.get(idx)
-                }
-                0xA000..0xC000 => {
-                    if !self.ram_enabled {return Some(&0xFFu8)};
-                    let bank = if self.banking_mode == 1 {self.rambank} else {0};
-                    let idx = (bank * 0x2000) | (index & 0x1FFF);
-                    self.ram.get(idx)
-                }
-                _ => self.memory.get(index)
-            }
-        } else {
-            None
+    pub fn get(&self, index: usize) -> Option<u8> {
+        match index {
+            0x0000..0x0100 if self.boot_rom_enabled => Some(self.boot_rom[index]),
+            0x0000..0x8000 => Some(self.patch_rom(index as u16, self.cartridge.read_rom(index as u16))),
+            0xA000..0xC000 => Some(self.cartridge.read_ram(index as u16)),
+            0xFF00 => Some(0xC0 | (self.memory[index] & 0x30) | self.joypad_lines()),
+            _ => self.memory.get(index).copied(),
         }
     }
 
+    /// Direct access to internal memory. The cartridge ranges are not backed by it.
     pub fn get_mut(&mut self, index: usize) -> Option<&mut u8> {
-        if self.mbc == MBC0 {
-            self.memory.get_mut(index)
-        } else if self.mbc == MBC1 {
-            match index{
-                0x0000..0x8000 => {
-                    let bank = if index < 0x4000 {
-                        if self.banking_mode == 0 {
-                            0
-                        }
-                        else {
-                            self.rombank & 0xE0
-                        }
-                    } else {
-                        self.rombank
-                    };
-                    let idx = (bank * 0x4000) | (index & 0x3FFF);
-                    self.rom.get_mut(idx)
-                }
-                0xA000..0xC000 => {
-                    if !self.ram_enabled {return None};
-                    let bank = if self.banking_mode == 1 {self.rambank} else {0};
-                    let idx = (bank * 0x2000) | (index & 0x1FFF);
-                    self.ram.get_mut(idx)
-                }
-                _ => self.memory.get_mut(index)
-            }
-        } else if self.mbc == MBC5 {
-            match index {
-                0x0000..0x8000 => {
-                    let bank = if index < 0x4000 {
-                        0
-                    } else {
-                        self.rombank & 0x1FF
-                    };
-                    let idx = (bank * 0x4000) | (index & 0x3FFF);
-                    self.rom.get_mut(idx)
-                }
-                0xA000..0xC000 => {
-                    if !self.ram_enabled {return None};
-                    let bank = if self.banking_mode == 1 {self.rambank} else {0};
-                    let idx = (bank * 0x2000) | (index & 0x1FFF);
-                    self.ram.get_mut(idx)
-                }
-                _ => self.memory.get_mut(index)
-            }
-        } else {
-            None
+        match index {
+            0x0000..0x8000 | 0xA000..0xC000 => None,
+            _ => self.memory.get_mut(index),
         }
     }
 
     pub fn write_memory(&mut self, address: usize, value: u8) {
         match address {
-            0x0000..0x8000 => {
-                if self.mbc == MBC1 {
-                    match address { 
-                        0x0000..0x2000 => self.ram_enabled = value & 0xF == 0xA,
-                        0x2000..0x4000 => {
-                            let lower_bits = match (value as usize) & 0x1F { 
-                                0 => 1,
-                                n => n
-                            };
-                            self.rombank = ((self.rombank & 0x60) | lower_bits) % self.rombanks;
-                        }
-                        0x4000..0x6000 => {
-                            if self.rombanks > 0x20 {
-                                let upper_bits = (value as usize & 0x03) % (self.rombanks >> 5);
-                                self.rombank = self.rombank & 0x1F | (upper_bits << 5);
-                            }
-                            if self.rambanks > 1 {
-                                self.rambank = (value as usize) & 0x03;
-                            }
-                        }
-                        0x6000..0x8000 => self.banking_mode = value & 0x01,
-                        _ => unreachable!()
-                    }
-                } else if self.mbc == MBC5 {
-                    match address {
-                        0x0000..0x2000 => self.ram_enabled = value & 0xF == 0xA,
-                        0x2000..0x3000 => self.rombank = (self.rombank & 0x100) | (value as usize),
-                        0x3000..0x4000 => self.rombank = (self.rombank & 0x0FF) | (value as usize),
-                        0x4000..0x6000 => self.rambank = (value & 0x0F) as usize,
-                        _ => {},
-                    }
-                }
-            }
-            0xA000..0xC000 => {
-                if self.mbc == MBC1 || self.mbc == MBC5 {
-                   if !self.ram_enabled {return}
-                    let bank = if self.banking_mode == 1 {self.rambank} else {0};
-                    let address = (bank * 0x2000) | (address & 0x1FFF);
-                    if address < self.ram.len() {
-                        self.ram[address] = value;
-                    }
-                }
-            }
+            0x0000..0x8000 => self.cartridge.write_rom(address as u16, value),
+            0xA000..0xC000 => self.cartridge.write_ram(address as u16, value),
             0xC000..=0xDDFF => {
                 self.memory[address] = value;
                 self.memory[address + 0x2000] = value;
@@ -253,23 +119,16 @@ impl Memory {
                 // Do nothing
             }
             0xFF00 => {
-                let current_inputs = match (value & 0x30) >> 4 {
-                    0 => (self.input_buffer & 0x0F) | (self.input_buffer >> 4), //both selected
-                    1 => self.input_buffer >> 4,                                //buttons selected
-                    2 => self.input_buffer & 0x0F,                              //d-pad selected
-                    3 => 0xF,                                                   //nothing selected
-                    _ => 0xF,
-                };
-                self.memory[address] = value | current_inputs;
+                let lines = self.joypad_lines();
+                self.memory[address] = value & 0x30;
+                self.request_joypad_interrupt(lines);
             }
             0xFF02 => {
-                if value == 0x81 {
-                    let byte = self.memory[0xFF01];
-                    self.serial_output.write_byte(byte);
-                    print!("{}", byte as char);
-                    self.memory[address] = 0x00;
-                } else {
-                    self.memory[address] = value;
+                self.memory[address] = value | 0x7E;
+                // With the internal clock this side drives the transfer; with the external
+                // one it waits for the partner to do it
+                if value & 0x81 == 0x81 {
+                    self.serial_cycles = 0;
                 }
             }
             0xFF04 => {
@@ -282,7 +141,7 @@ impl Memory {
                 for i in 0..0xA0 {
                     let src = source_start + i;
                     let dest = 0xFE00 + i;
-                    self.memory[dest as usize] = self.memory[src as usize];
+                    self.memory[dest as usize] = self.get(src as usize).unwrap_or(0xFF);
                 }
                 self.memory[address] = value;
             }
@@ -323,6 +182,46 @@ impl Memory {
         }
     }
 
+    fn update_serial(&mut self, cycles: u64) {
+        let control = self.memory[0xFF02] & 0x81;
+        if self.serial_reply_pending {
+            // Polled even if SC changed meanwhile, so the device stops waiting as well
+            if let Some(byte) = self.serial_device.poll(self.memory[0xFF01]) {
+                self.serial_reply_pending = false;
+                if control == 0x81 {
+                    self.finish_serial(byte);
+                }
+            }
+            return;
+        }
+
+        match control {
+            // Only polled once armed, so a byte the partner clocks in early stays with
+            // the device until then
+            0x80 => {
+                if let Some(byte) = self.serial_device.poll(self.memory[0xFF01]) {
+                    self.finish_serial(byte);
+                }
+            }
+            0x81 => {
+                self.serial_cycles += cycles;
+                if self.serial_cycles >= SERIAL_TRANSFER_CYCLES {
+                    match self.serial_device.transfer(self.memory[0xFF01]) {
+                        Some(byte) => self.finish_serial(byte),
+                        None => self.serial_reply_pending = true,
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn finish_serial(&mut self, received: u8) {
+        self.memory[0xFF01] = received;
+        self.memory[0xFF02] &= 0x7F;
+        self.memory[0xFF0F] |= 0x08;
+    }
+
     fn tac_enabled(&self) -> bool {
         self.memory[0xFF07] & 0x04 != 0
     }
@@ -336,55 +235,198 @@ impl Memory {
         }
     }
 
-    pub fn write_cartridge(&mut self, cartridge_data: &[u8]) {
-        let rom = read_cartridge("resources/boot/dmg_boot.bin".to_string());
-
-        let data_len = cartridge_data.len();
-        self.start_cartridge
-            .copy_from_slice(&cartridge_data[0x0000..=0x00FF]);
-        
-        if self.mbc == MBC0 {
-            self.memory[0x0000..=0x00FF].copy_from_slice(&rom);
-            self.memory[0x0100..data_len].copy_from_slice(&cartridge_data[0x0100..data_len]);
-        } else if self.mbc == MBC1 || self.mbc == MBC5 {
-            self.rom = cartridge_data.to_vec();
-            self.rom[0x0000..=0x00FF].copy_from_slice(&rom);
-            self.rombanks = if self.rom[0x148] <= 8 { 2 << self.rom[0x148] } else { 0 };
-            self.rambanks = match self.rom[0x149] {
-                1 => 1,
-                2 => 1,
-                3 => 4,
-                4 => 16,
-                5 => 8,
-                _ => 0,
+    pub fn insert_cartridge(&mut self, cartridge: Box<dyn Cartridge>) {
+        let boot_rom = read_cartridge("resources/boot/dmg_boot.bin".to_string());
+        self.boot_rom.copy_from_slice(&boot_rom[0x0000..=0x00FF]);
+        self.boot_rom_enabled = true;
+        self.cartridge = cartridge;
+    }
+
+    /// Game Genie codes sit between the cartridge and the bus and replace matching reads.
+    fn patch_rom(&self, address: u16, value: u8) -> u8 {
+        if !self.cheats_enabled {
+            return value;
+        }
+        for cheat in &self.cheats {
+            if let Cheat::GameGenie { address: patched, value: new_value, compare } = *cheat
+                && patched == address
+                && compare.is_none_or(|compare| compare == value)
+            {
+                return new_value;
+            }
+        }
+        value
+    }
+
+    /// GameShark codes write their values once per frame.
+    pub(crate) fn apply_game_shark(&mut self) {
+        if !self.cheats_enabled {
+            return;
+        }
+        for index in 0..self.cheats.len() {
+            let Cheat::GameShark { bank, address, value } = self.cheats[index] else {
+                continue;
             };
-            self.ram.resize(self.rambanks * 0x2000, 0u8);
+            match address {
+                0x0000..0x8000 => (),
+                0xA000..0xC000 => {
+                    let offset = match bank {
+                        Some(bank) => Some(bank as usize * RAM_BANK_SIZE + (address as usize & 0x1FFF)),
+                        None => self.cartridge.mapped_ram_index(address),
+                    };
+                    let ram = self.cartridge.ram_mut();
+                    if let Some(offset) = offset
+                        && !ram.is_empty()
+                    {
+                        let length = ram.len();
+                        ram[offset % length] = value;
+                    }
+                }
+                _ => self.write_memory(address as usize, value),
+            }
         }
     }
 
-    pub fn disable_rom(&mut self) {
-        if self.mbc == MBC0 {
-            self.memory[0x0000..=0x00FF].copy_from_slice(&self.start_cartridge);
-        } else if self.mbc == MBC1 || self.mbc == MBC5 {
-            self.rom[0x0000..=0x00FF].copy_from_slice(&self.start_cartridge);
+    /// Buttons are active low: bits 0-3 are the d-pad, bits 4-7 A, B, Select and Start.
+    pub(crate) fn set_inputs(&mut self, inputs: u8) {
+        let lines = self.joypad_lines();
+        self.input_buffer = inputs;
+        self.request_joypad_interrupt(lines);
+    }
+
+    /// Whether a button in one of the selected rows is held, which is what ends STOP.
+    pub(crate) fn joypad_active(&self) -> bool {
+        self.joypad_lines() != 0x0F
+    }
+
+    /// The low nibble of P1: every row whose select bit is low pulls its held buttons low.
+    fn joypad_lines(&self) -> u8 {
+        let select = self.memory[0xFF00];
+        let mut lines = 0x0F;
+        if select & 0x10 == 0 {
+            lines &= self.input_buffer & 0x0F;
+        }
+        if select & 0x20 == 0 {
+            lines &= self.input_buffer >> 4;
+        }
+        lines
+    }
+
+    /// The joypad interrupt fires when any line goes from high to low.
+    fn request_joypad_interrupt(&mut self, previous_lines: u8) {
+        if previous_lines & !self.joypad_lines() != 0 {
+            self.memory[0xFF0F] |= 0x10;
         }
     }
 
+    pub(crate) fn cartridge(&self) -> &dyn Cartridge {
+        self.cartridge.as_ref()
+    }
+
+    pub(crate) fn cartridge_mut(&mut self) -> &mut dyn Cartridge {
+        self.cartridge.as_mut()
+    }
+
+    pub fn disable_rom(&mut self) {
+        self.boot_rom_enabled = false;
+    }
+
+    pub(crate) fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.memory);
+        state.write_bool(self.boot_rom_enabled);
+        state.write_u64(self.cycles_div);
+        state.write_u64(self.cycles_tima);
+        state.write_u64(self.serial_cycles);
+        state.write_u8(self.input_buffer);
+        self.cartridge.save_state(state);
+    }
+
+    pub(crate) fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        state.read_bytes_into(&mut self.memory)?;
+        self.boot_rom_enabled = state.read_bool()?;
+        self.cycles_div = state.read_u64()?;
+        self.cycles_tima = state.read_u64()?;
+        self.serial_cycles = state.read_u64()?;
+        self.serial_reply_pending = false;
+        self.input_buffer = state.read_u8()?;
+        self.cartridge.load_state(state)
+    }
+
     pub fn get_serial_output(&self) -> &SerialOutput {
-        &self.serial_output
+        (self.serial_device.as_ref() as &dyn Any)
+            .downcast_ref()
+            .expect("Another serial device is plugged in")
     }
+}
 
-    pub(crate) fn select_mbc(&mut self, code: u8) {
-        self.mbc = match code {
-            0x00 => MBC0,
-            0x01..=0x03 => MBC1,
-            0x05..=0x06 => MBC2,
-            0x0B..=0x0D => MMM01,
-            0x0F..=0x13 => MBC3,
-            0x19..=0x1E => MBC5,
-            0x20 => MBC6,
-            0x22 => MBC7,
-            _ => MBC0,
-        };
+impl Bus for Memory {
+    fn read(&mut self, address: u16) -> u8 {
+        self.get(address as usize).unwrap_or(0xFF)
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self.write_memory(address as usize, value);
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        self.update_timer(cycles);
+        self.update_serial(cycles);
+        self.cartridge.tick(cycles);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::cartridge;
+
+    #[test]
+    fn cheats_patch_rom_and_write_ram() {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0147] = 0x03;
+        rom[0x0149] = 0x03;
+        let mut memory = Memory::new();
+        memory.cartridge = cartridge::from_rom(rom).unwrap();
+        memory.write_memory(0x0000, 0x0A);
+
+        memory.cheats.push("3EF-DFF".parse().unwrap());
+        memory.cheats.push("01A-BCF-E6E".parse().unwrap());
+        assert_eq!(memory.get(0x0FDF), Some(0x3E));
+        // The compare byte doesn't match, so the ROM shows through
+        assert_eq!(memory.get(0x0ABC), Some(0x00));
+
+        memory.cheats.push("0142E1C0".parse().unwrap());
+        memory.cheats.push("8299FFA0".parse().unwrap());
+        memory.apply_game_shark();
+        assert_eq!(memory.get(0xC0E1), Some(0x42));
+        assert_eq!(memory.get(0xA0FF), Some(0x00));
+        memory.write_memory(0x6000, 0x01);
+        memory.write_memory(0x4000, 0x02);
+        assert_eq!(memory.get(0xA0FF), Some(0x99));
+
+        memory.cheats_enabled = false;
+        assert_eq!(memory.get(0x0FDF), Some(0x00));
+    }
+
+    #[test]
+    fn serial_transfer_takes_4096_cycles() {
+        let mut memory = Memory::new();
+        memory.write_memory(0xFF0F, 0x00);
+        memory.write_memory(0xFF01, 0x42);
+        memory.write_memory(0xFF02, 0x81);
+        memory.tick(SERIAL_TRANSFER_CYCLES - 4);
+        assert_eq!(memory.get(0xFF02), Some(0xFF));
+        assert_eq!(memory.get(0xFF0F), Some(0x00));
+
+        memory.tick(4);
+        assert_eq!(memory.get(0xFF01), Some(0xFF));
+        assert_eq!(memory.get(0xFF02), Some(0x7F));
+        assert_eq!(memory.get(0xFF0F), Some(0x08));
+        assert_eq!(memory.get_serial_output().get_output(), "B");
+
+        // Nobody drives the external clock
+        memory.write_memory(0xFF02, 0x80);
+        memory.tick(SERIAL_TRANSFER_CYCLES * 2);
+        assert_eq!(memory.get(0xFF02), Some(0xFE));
     }
 }
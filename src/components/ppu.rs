@@ -1,4 +1,5 @@
 use crate::components::memory::Memory;
+use crate::io::savestate::{StateError, StateReader, StateWriter};
 use crate::components::ppu::PpuMode::*;
 use crate::window::emulator_app::{HEIGHT, WIDTH};
 use std::cmp::PartialEq;
@@ -11,6 +12,27 @@ enum PpuMode {
     VBlank,
 }
 
+impl PpuMode {
+    fn to_u8(&self) -> u8 {
+        match self {
+            HBlank => 0,
+            VBlank => 1,
+            OAMScan => 2,
+            PixelDrawing => 3,
+        }
+    }
+
+    fn from_u8(value: u8) -> Result<Self, StateError> {
+        match value {
+            0 => Ok(HBlank),
+            1 => Ok(VBlank),
+            2 => Ok(OAMScan),
+            3 => Ok(PixelDrawing),
+            _ => Err(StateError::Mismatch("PPU mode")),
+        }
+    }
+}
+
 pub struct PPU {
     prev_mode: PpuMode,
     mode: PpuMode,
@@ -34,6 +56,27 @@ impl PPU {
         }
     }
 
+    pub(crate) fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.prev_mode.to_u8());
+        state.write_u8(self.mode.to_u8());
+        state.write_bytes(&self.framebuffer);
+        state.write_u8(self.prev_line);
+        state.write_u8(self.line);
+        state.write_u64(self.mode_clock);
+        state.write_u8(self.window_line_counter);
+    }
+
+    pub(crate) fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.prev_mode = PpuMode::from_u8(state.read_u8()?)?;
+        self.mode = PpuMode::from_u8(state.read_u8()?)?;
+        state.read_bytes_into(&mut self.framebuffer)?;
+        self.prev_line = state.read_u8()?;
+        self.line = state.read_u8()?;
+        self.mode_clock = state.read_u64()?;
+        self.window_line_counter = state.read_u8()?;
+        Ok(())
+    }
+
     pub(crate) fn step(&mut self, cycles: u64, memory: &mut Memory) {
         self.mode_clock += cycles;
         while self.mode_clock > 0 {
@@ -84,7 +127,7 @@ impl PPU {
                             self.window_line_counter = 0;
                             
                             if let Some(lcdc) = memory.get(0xFF40) {
-                                if (*lcdc & 0x80) != 0 {
+                                if (lcdc & 0x80) != 0 {
                                     if let Some(flag) = memory.get_mut(0xFF0F) {
                                         *flag |= 0x01;
                                     }
@@ -126,8 +169,8 @@ impl PPU {
     }
 
     fn update_stat(&mut self, memory: &mut Memory) {
-        let lcdc = memory.get(0xFF40).copied().unwrap_or(0);
-        let lyc = memory.get(0xFF45).copied().unwrap_or(0);
+        let lcdc = memory.get(0xFF40).unwrap_or(0);
+        let lyc = memory.get(0xFF45).unwrap_or(0);
 
         if (lcdc & 0x80) == 0 {
             self.line = 0;
@@ -164,7 +207,7 @@ impl PPU {
 
         if self.mode != self.prev_mode {
             let mut trigger_interrupt = false;
-            let stat = memory.get(0xFF41).unwrap_or(&0);
+            let stat = memory.get(0xFF41).unwrap_or(0);
 
             match self.mode {
                 OAMScan if (stat & 0x20) != 0 => trigger_interrupt = true, // Mode 2
@@ -182,7 +225,7 @@ impl PPU {
     }
 
     fn render_scanline(&mut self, memory: &Memory) {
-        let lcdc = memory.get(0xFF40).copied().unwrap_or(0);
+        let lcdc = memory.get(0xFF40).unwrap_or(0);
         if (lcdc & 0x80) == 0 {
             return;
         }
@@ -196,12 +239,12 @@ impl PPU {
         let window_tile_map = if (lcdc & 0x40) != 0 { 0x9C00 } else { 0x9800 };
         let tile_data = if (lcdc & 0x10) != 0 { 0x8000 } else { 0x8800 };
 
-        let scy = memory.get(0xFF42).copied().unwrap_or(0);
-        let scx = memory.get(0xFF43).copied().unwrap_or(0);
-        let wy = memory.get(0xFF4A).copied().unwrap_or(0);
-        let wx = memory.get(0xFF4B).copied().unwrap_or(0).wrapping_sub(7);
+        let scy = memory.get(0xFF42).unwrap_or(0);
+        let scx = memory.get(0xFF43).unwrap_or(0);
+        let wy = memory.get(0xFF4A).unwrap_or(0);
+        let wx = memory.get(0xFF4B).unwrap_or(0).wrapping_sub(7);
 
-        let wx_effective = memory.get(0xFF4B).copied().unwrap_or(0);
+        let wx_effective = memory.get(0xFF4B).unwrap_or(0);
         let window_visible = window_enable && self.line >= wy && (7..=166).contains(&wx_effective);
 
         if window_visible {
@@ -222,7 +265,7 @@ impl PPU {
             let tile_x = (pixel_x as u16) / 8;
             let tile_y = (pixel_y as u16) / 8;
             let tile_address = tile_map + tile_y * 32 + tile_x;
-            let tile_num = memory.get(tile_address as usize).copied().unwrap_or(0) as u16;
+            let tile_num = memory.get(tile_address as usize).unwrap_or(0) as u16;
 
             let tile_data_address = if tile_data == 0x8000 {
                 tile_data + tile_num * 16
@@ -233,11 +276,9 @@ impl PPU {
             let row = (pixel_y % 8) as u16 * 2;
             let byte1 = memory
                 .get(tile_data_address as usize + row as usize)
-                .copied()
                 .unwrap_or(0);
             let byte2 = memory
                 .get(tile_data_address as usize + row as usize + 1)
-                .copied()
                 .unwrap_or(0);
 
             let bit_index = 7 - (pixel_x as u16 % 8);
@@ -245,7 +286,7 @@ impl PPU {
             let color_bit_high = (byte2 >> bit_index) & 1;
             let color_id = (color_bit_high << 1) | color_bit_low;
 
-            let bgp = memory.get(0xFF47).copied().unwrap_or(0);
+            let bgp = memory.get(0xFF47).unwrap_or(0);
             let (red, green, blue) = if !bg_window_enable {
                 (0x9A, 0x9E, 0x3F)
             } else {
@@ -270,10 +311,10 @@ impl PPU {
             let sprite_height = if obj_size { 16 } else { 8 };
             for i in 0..40 {
                 let sprite_index = i * 4;
-                let y_pos = memory.get(0xFE00 + sprite_index).copied().unwrap_or(0);
-                let x_pos = memory.get(0xFE01 + sprite_index).copied().unwrap_or(0);
-                let mut tile_num = memory.get(0xFE02 + sprite_index).copied().unwrap_or(0);
-                let attributes = memory.get(0xFE03 + sprite_index).copied().unwrap_or(0);
+                let y_pos = memory.get(0xFE00 + sprite_index).unwrap_or(0);
+                let x_pos = memory.get(0xFE01 + sprite_index).unwrap_or(0);
+                let mut tile_num = memory.get(0xFE02 + sprite_index).unwrap_or(0);
+                let attributes = memory.get(0xFE03 + sprite_index).unwrap_or(0);
 
                 if obj_size {
                     tile_num &= 0xFE;
@@ -297,10 +338,9 @@ impl PPU {
                     * 2;
 
                 let tile_data_address = 0x8000 + (tile_num as u16 * 16) + row;
-                let byte1 = memory.get(tile_data_address as usize).copied().unwrap_or(0);
+                let byte1 = memory.get(tile_data_address as usize).unwrap_or(0);
                 let byte2 = memory
                     .get(tile_data_address as usize + 1)
-                    .copied()
                     .unwrap_or(0);
 
                 for x in 0..8 {
@@ -314,9 +354,9 @@ impl PPU {
                     }
 
                     let obp = if attributes & 0x10 != 0 {
-                        memory.get(0xFF49).copied().unwrap_or(0)
+                        memory.get(0xFF49).unwrap_or(0)
                     } else {
-                        memory.get(0xFF48).copied().unwrap_or(0)
+                        memory.get(0xFF48).unwrap_or(0)
                     };
 
                     let (red, green, blue) = match (obp >> (color_id * 2)) & 0b11 {
@@ -346,4 +386,8 @@ impl PPU {
     pub fn copy_to_framebuffer(&self, output: &mut [u8]) {
         output.copy_from_slice(&self.framebuffer);
     }
+
+    pub(crate) fn framebuffer(&self) -> &[u8] {
+        &self.framebuffer
+    }
 }
@@ -0,0 +1,223 @@
+use crate::components::gameboy::Gameboy;
+use std::str::FromStr;
+
+/// What a search covers: cartridge RAM as currently banked in, WRAM and HRAM.
+const REGIONS: [(u16, u16); 3] = [(0xA000, 0xC000), (0xC000, 0xE000), (0xFF80, 0xFFFF)];
+
+/// How the bytes at an address are read as a value.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ValueType {
+    U8,
+    U16Le,
+    U16Be,
+}
+
+impl ValueType {
+    fn size(self) -> u16 {
+        match self {
+            ValueType::U8 => 1,
+            ValueType::U16Le | ValueType::U16Be => 2,
+        }
+    }
+
+    /// Reads through the memory map, so external RAM is whatever bank the game mapped.
+    pub fn read(self, gameboy: &Gameboy, address: u16) -> u16 {
+        let byte = |offset: u16| gameboy.peek(address.wrapping_add(offset)) as u16;
+        match self {
+            ValueType::U8 => byte(0),
+            ValueType::U16Le => byte(0) | (byte(1) << 8),
+            ValueType::U16Be => (byte(0) << 8) | byte(1),
+        }
+    }
+}
+
+impl FromStr for ValueType {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "8" => Ok(ValueType::U8),
+            "16" | "16le" => Ok(ValueType::U16Le),
+            "16be" => Ok(ValueType::U16Be),
+            _ => Err(format!(
+                "Unknown value type {name}, expected 8, 16le or 16be"
+            )),
+        }
+    }
+}
+
+/// Compares each candidate's current value against the one from the last snapshot.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Filter {
+    Equal,
+    Changed,
+    Increased,
+    Decreased,
+    Value(u16),
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Candidate {
+    pub address: u16,
+    /// The value at the last snapshot.
+    pub value: u16,
+}
+
+/// Narrows down which address holds a value by filtering snapshots taken over time.
+pub struct RamSearch {
+    value_type: ValueType,
+    candidates: Vec<Candidate>,
+}
+
+impl RamSearch {
+    /// Starts with every address as a candidate.
+    pub fn new(gameboy: &Gameboy, value_type: ValueType) -> Self {
+        let candidates = REGIONS
+            .iter()
+            .flat_map(|&(start, end)| start..=end - value_type.size())
+            .map(|address| Candidate {
+                address,
+                value: value_type.read(gameboy, address),
+            })
+            .collect();
+        RamSearch {
+            value_type,
+            candidates,
+        }
+    }
+
+    /// Keeps the candidates that pass `filter` and snapshots their current values.
+    pub fn filter(&mut self, gameboy: &Gameboy, filter: Filter) {
+        self.candidates.retain_mut(|candidate| {
+            let value = self.value_type.read(gameboy, candidate.address);
+            let keep = match filter {
+                Filter::Equal => value == candidate.value,
+                Filter::Changed => value != candidate.value,
+                Filter::Increased => value > candidate.value,
+                Filter::Decreased => value < candidate.value,
+                Filter::Value(wanted) => value == wanted,
+            };
+            candidate.value = value;
+            keep
+        });
+    }
+
+    pub fn candidates(&self) -> &[Candidate] {
+        &self.candidates
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct WatchChange {
+    pub frame: u64,
+    pub address: u16,
+    pub old: u16,
+    pub new: u16,
+}
+
+struct Watch {
+    address: u16,
+    value_type: ValueType,
+    value: u16,
+}
+
+/// Addresses checked once per frame, with a log of every change.
+#[derive(Default)]
+pub struct Watches {
+    watches: Vec<Watch>,
+    log: Vec<WatchChange>,
+}
+
+impl Watches {
+    pub fn add(&mut self, gameboy: &Gameboy, address: u16, value_type: ValueType) {
+        self.remove(address);
+        self.watches.push(Watch {
+            address,
+            value_type,
+            value: value_type.read(gameboy, address),
+        });
+    }
+
+    pub fn remove(&mut self, address: u16) -> bool {
+        let count = self.watches.len();
+        self.watches.retain(|watch| watch.address != address);
+        self.watches.len() != count
+    }
+
+    /// Compares every watch with the previous frame and returns the changes, which are
+    /// also added to the log.
+    pub fn update(&mut self, frame: u64, gameboy: &Gameboy) -> &[WatchChange] {
+        let start = self.log.len();
+        for watch in &mut self.watches {
+            let value = watch.value_type.read(gameboy, watch.address);
+            if value != watch.value {
+                self.log.push(WatchChange {
+                    frame,
+                    address: watch.address,
+                    old: watch.value,
+                    new: value,
+                });
+                watch.value = value;
+            }
+        }
+        &self.log[start..]
+    }
+
+    pub fn log(&self) -> &[WatchChange] {
+        &self.log
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filters_narrow_down_candidates() {
+        let mut gameboy = Gameboy::new();
+        let mut search = RamSearch::new(&gameboy, ValueType::U16Be);
+        assert_eq!(search.candidates().len(), 0x1FFF + 0x1FFF + 0x7E);
+
+        gameboy.poke(0xC100, 0x12);
+        gameboy.poke(0xC101, 0x34);
+        gameboy.poke(0xFF90, 0x01);
+        search.filter(&gameboy, Filter::Changed);
+        assert!(search.candidates().iter().all(|candidate| {
+            (0xC0FF..=0xC101).contains(&candidate.address)
+                || (0xFF8F..=0xFF90).contains(&candidate.address)
+        }));
+
+        gameboy.poke(0xC101, 0x35);
+        search.filter(&gameboy, Filter::Increased);
+        search.filter(&gameboy, Filter::Value(0x1235));
+        assert_eq!(
+            search.candidates(),
+            &[Candidate {
+                address: 0xC100,
+                value: 0x1235,
+            }]
+        );
+    }
+
+    #[test]
+    fn watches_log_changes() {
+        let mut gameboy = Gameboy::new();
+        let mut watches = Watches::default();
+        watches.add(&gameboy, 0xC000, ValueType::U16Le);
+        assert!(watches.update(0, &gameboy).is_empty());
+
+        gameboy.poke(0xC001, 0x02);
+        let change = WatchChange {
+            frame: 1,
+            address: 0xC000,
+            old: 0xFFFF,
+            new: 0x02FF,
+        };
+        assert_eq!(watches.update(1, &gameboy), &[change]);
+        assert!(watches.update(2, &gameboy).is_empty());
+        assert!(watches.remove(0xC000));
+        gameboy.poke(0xC001, 0x03);
+        assert!(watches.update(3, &gameboy).is_empty());
+        assert_eq!(watches.log(), &[change]);
+    }
+}
@@ -1,2 +1,10 @@
+pub mod battery;
+pub mod camera_source;
 pub mod cartridge_reader;
+pub mod cheat_file;
+pub mod link;
+pub mod movie;
+pub mod printer;
+pub mod serial_device;
 pub mod serialoutput;
+pub mod savestate;
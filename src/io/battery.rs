@@ -0,0 +1,20 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Battery-backed RAM lives next to the ROM as `<name>.sav`, like most emulators keep it.
+pub fn save_path(rom_path: &Path) -> PathBuf {
+    rom_path.with_extension("sav")
+}
+
+pub fn load(rom_path: &Path) -> Option<Vec<u8>> {
+    fs::read(save_path(rom_path)).ok()
+}
+
+/// Writes through a temporary file so a crash halfway leaves the old save intact.
+pub fn store(rom_path: &Path, data: &[u8]) -> io::Result<()> {
+    let path = save_path(rom_path);
+    let temporary = path.with_extension("sav.tmp");
+    fs::write(&temporary, data)?;
+    fs::rename(&temporary, &path)
+}
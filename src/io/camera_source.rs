@@ -0,0 +1,62 @@
+use crate::components::cartridge::{CameraSource, SENSOR_HEIGHT, SENSOR_WIDTH};
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Loads a PNG as the Pocket Camera's picture, or every PNG in a directory, in name
+/// order, as a sequence with one picture per capture. Pictures are converted to
+/// grayscale and stretched to the sensor's size.
+pub fn load(path: &Path) -> Result<CameraSource, png::DecodingError> {
+    let paths = if path.is_dir() {
+        let mut paths: Vec<PathBuf> = fs::read_dir(path)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                path.extension()
+                    .is_some_and(|extension| extension.eq_ignore_ascii_case("png"))
+            })
+            .collect();
+        paths.sort();
+        paths
+    } else {
+        vec![path.to_path_buf()]
+    };
+    if paths.is_empty() {
+        let error = io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("No PNG files in {}", path.display()),
+        );
+        return Err(error.into());
+    }
+
+    let frames = paths
+        .iter()
+        .map(|path| load_frame(path))
+        .collect::<Result<_, _>>()?;
+    Ok(CameraSource::new(frames))
+}
+
+fn load_frame(path: &Path) -> Result<Vec<u8>, png::DecodingError> {
+    let mut decoder = png::Decoder::new(File::open(path)?);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info()?;
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut pixels)?;
+
+    let channels = info.color_type.samples();
+    let (width, height) = (info.width as usize, info.height as usize);
+    let luma = |x: usize, y: usize| {
+        let pixel = &pixels[y * info.line_size + x * channels..][..channels];
+        match pixel {
+            [r, g, b, ..] => {
+                ((*r as u32 * 299 + *g as u32 * 587 + *b as u32 * 114) / 1000) as u8
+            }
+            [gray, ..] => *gray,
+            [] => 0,
+        }
+    };
+
+    Ok((0..SENSOR_HEIGHT)
+        .flat_map(|y| (0..SENSOR_WIDTH).map(move |x| (x, y)))
+        .map(|(x, y)| luma(x * width / SENSOR_WIDTH, y * height / SENSOR_HEIGHT))
+        .collect())
+}
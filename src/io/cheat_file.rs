@@ -0,0 +1,16 @@
+use crate::components::cheats::{self, Cheat, CheatError};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Cheats for a ROM live next to it as `<name>.cht`.
+pub fn path(rom_path: &Path) -> PathBuf {
+    rom_path.with_extension("cht")
+}
+
+/// The cheats for the ROM at `rom_path`, none if it has no readable cheat file.
+pub fn load(rom_path: &Path) -> Result<Vec<Cheat>, CheatError> {
+    match fs::read_to_string(path(rom_path)) {
+        Ok(text) => cheats::parse_list(&text),
+        Err(_) => Ok(Vec::new()),
+    }
+}
@@ -0,0 +1,235 @@
+use crate::io::serial_device::SerialDevice;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{Ipv4Addr, Shutdown, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long the clocking side waits for the partner's byte before reading 0xFF, as if
+/// nothing was plugged in.
+const REPLY_TIMEOUT: Duration = Duration::from_millis(100);
+
+const TRANSFER: u8 = 0;
+const REPLY: u8 = 1;
+
+enum Message {
+    /// The partner clocked a transfer and sent this byte.
+    Transfer(u8),
+    /// The partner's byte for a transfer we clocked.
+    Reply(u8),
+}
+
+struct Connection {
+    stream: TcpStream,
+    messages: Receiver<Message>,
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        // The reader thread holds a clone of the socket, so close it for both
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
+
+/// A link cable to another emulator process over a localhost TCP socket.
+///
+/// The side using the internal clock sends its byte and picks up the partner's through
+/// `poll`, so emulation carries on while it is on its way. Replies are sent from the
+/// reader thread with whatever the partner last put in SB, so the clocking side only
+/// waits for a round trip and not for the partner's next frame.
+pub struct TcpLink {
+    /// Kept until the partner connects; until then the cable reads as unplugged.
+    listener: Option<TcpListener>,
+    connection: Option<Connection>,
+    /// What the reader thread replies with when the partner clocks a transfer.
+    outgoing: Arc<AtomicU8>,
+    /// Transfers we clocked that the partner hasn't answered yet.
+    unanswered: usize,
+    /// When the transfer the Game Boy is waiting on went out.
+    waiting_since: Option<Instant>,
+}
+
+impl TcpLink {
+    /// Listens on `port` without waiting; the partner is picked up whenever it connects.
+    pub fn listen(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+        listener.set_nonblocking(true)?;
+        println!(
+            "Waiting for a link partner on port {}",
+            listener.local_addr()?.port()
+        );
+        Ok(TcpLink {
+            listener: Some(listener),
+            ..Self::unplugged()
+        })
+    }
+
+    pub fn connect(port: u16) -> io::Result<Self> {
+        let mut link = Self::unplugged();
+        link.attach(TcpStream::connect((Ipv4Addr::LOCALHOST, port))?)?;
+        Ok(link)
+    }
+
+    fn unplugged() -> Self {
+        TcpLink {
+            listener: None,
+            connection: None,
+            outgoing: Arc::new(AtomicU8::new(0xFF)),
+            unanswered: 0,
+            waiting_since: None,
+        }
+    }
+
+    fn attach(&mut self, stream: TcpStream) -> io::Result<()> {
+        // Every message is two bytes and waiting on Nagle would stall every transfer
+        stream.set_nodelay(true)?;
+        let mut reader = stream.try_clone()?;
+        let mut writer = stream.try_clone()?;
+        let reply = Arc::clone(&self.outgoing);
+        let (tx_messages, messages) = mpsc::channel();
+
+        thread::spawn(move || {
+            let mut message = [0; 2];
+            while reader.read_exact(&mut message).is_ok() {
+                let (message, answer) = match message {
+                    [TRANSFER, byte] => (Message::Transfer(byte), true),
+                    [REPLY, byte] => (Message::Reply(byte), false),
+                    _ => break,
+                };
+                // Queued before answering, so the byte is there once the partner has ours
+                if tx_messages.send(message).is_err() {
+                    break;
+                }
+                if answer
+                    && writer
+                        .write_all(&[REPLY, reply.load(Ordering::Relaxed)])
+                        .is_err()
+                {
+                    break;
+                }
+            }
+        });
+
+        self.connection = Some(Connection { stream, messages });
+        Ok(())
+    }
+
+    /// Picks up the partner if it connected to our listener in the meantime.
+    fn accept(&mut self) {
+        let Some(listener) = &self.listener else {
+            return;
+        };
+        let stream = match listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(error) if error.kind() == ErrorKind::WouldBlock => return,
+            Err(error) => {
+                eprintln!("Failed to accept the link partner: {error}");
+                self.listener = None;
+                return;
+            }
+        };
+        self.listener = None;
+        // Some platforms hand out sockets that are non-blocking like the listener
+        match stream
+            .set_nonblocking(false)
+            .and_then(|()| self.attach(stream))
+        {
+            Ok(()) => println!("Link partner connected"),
+            Err(error) => eprintln!("Failed to set up the link partner: {error}"),
+        }
+    }
+
+    fn disconnect(&mut self) {
+        if self.connection.take().is_some() {
+            eprintln!("Link partner disconnected");
+        }
+        self.unanswered = 0;
+    }
+}
+
+impl SerialDevice for TcpLink {
+    /// Sends `outgoing` to the partner; its byte comes in through `poll`.
+    fn transfer(&mut self, outgoing: u8) -> Option<u8> {
+        self.accept();
+        let Some(connection) = &mut self.connection else {
+            return Some(0xFF);
+        };
+        if connection.stream.write_all(&[TRANSFER, outgoing]).is_err() {
+            self.disconnect();
+            return Some(0xFF);
+        }
+        self.unanswered += 1;
+        self.waiting_since = Some(Instant::now());
+        None
+    }
+
+    /// Takes the partner's byte for our transfer while waiting on one, and otherwise
+    /// the byte of a transfer the partner clocked.
+    fn poll(&mut self, outgoing: u8) -> Option<u8> {
+        self.outgoing.store(outgoing, Ordering::Relaxed);
+        self.accept();
+        while let Some(connection) = &self.connection {
+            match connection.messages.try_recv() {
+                Ok(Message::Transfer(byte)) if self.waiting_since.is_none() => {
+                    return Some(byte);
+                }
+                // Both sides clocked at once; the reader thread already answered
+                Ok(Message::Transfer(_)) => (),
+                Ok(Message::Reply(byte)) => {
+                    self.unanswered = self.unanswered.saturating_sub(1);
+                    // Earlier replies are late answers to transfers that timed out
+                    if self.unanswered == 0 && self.waiting_since.take().is_some() {
+                        return Some(byte);
+                    }
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => self.disconnect(),
+            }
+        }
+
+        let timed_out = self
+            .waiting_since
+            .is_some_and(|sent| self.connection.is_none() || sent.elapsed() >= REPLY_TIMEOUT);
+        if timed_out {
+            self.waiting_since = None;
+            return Some(0xFF);
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Clocks a transfer from `link` and waits for the partner's byte.
+    fn exchange(link: &mut TcpLink, outgoing: u8) -> u8 {
+        link.transfer(outgoing).unwrap_or_else(|| {
+            loop {
+                if let Some(byte) = link.poll(outgoing) {
+                    break byte;
+                }
+                thread::yield_now();
+            }
+        })
+    }
+
+    #[test]
+    fn transfers_exchange_bytes() {
+        let mut host = TcpLink::listen(0).unwrap();
+        let port = host.listener.as_ref().unwrap().local_addr().unwrap().port();
+        let mut client = TcpLink::connect(port).unwrap();
+
+        // The listener only picks the partner up once it is used
+        assert!(host.connection.is_none());
+        assert_eq!(client.poll(0x34), None);
+        assert_eq!(exchange(&mut host, 0x12), 0x34);
+        assert_eq!(client.poll(0x34), Some(0x12));
+        assert_eq!(client.poll(0x34), None);
+
+        drop(client);
+        assert_eq!(exchange(&mut host, 0x12), 0xFF);
+    }
+}
@@ -0,0 +1,294 @@
+use crate::components::cheats::Cheat;
+use crate::components::gameboy::Gameboy;
+use crate::io::savestate::{StateError, StateReader, StateWriter};
+use std::error::Error;
+use std::fmt;
+
+const MOVIE_MAGIC: [u8; 4] = *b"GBMV";
+const MOVIE_VERSION: u8 = 2;
+
+/// Where playback begins.
+#[derive(Clone, PartialEq, Debug)]
+pub enum MovieStart {
+    /// A fresh power-on with this battery RAM in the cartridge, empty if it has none.
+    PowerOn(Vec<u8>),
+    /// An embedded save state.
+    SaveState(Vec<u8>),
+}
+
+/// A recorded session: the joypad byte of every frame, played back from a known start.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Movie {
+    pub rom_sha1: String,
+    pub start: MovieStart,
+    /// Cheats active while recording; playback uses these instead of the cheat file.
+    pub cheats: Vec<Cheat>,
+    pub cheats_enabled: bool,
+    pub inputs: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub enum MovieError {
+    Format(StateError),
+    /// The movie was recorded with another ROM.
+    RomMismatch {
+        expected: String,
+        actual: String,
+    },
+    /// The embedded save state does not fit this emulator.
+    Start(StateError),
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MovieError::Format(error) => write!(f, "Invalid movie: {error}"),
+            MovieError::RomMismatch { expected, actual } => write!(
+                f,
+                "Movie was recorded with ROM {expected} but {actual} is loaded"
+            ),
+            MovieError::Start(error) => write!(f, "Failed to restore movie start: {error}"),
+        }
+    }
+}
+
+impl Error for MovieError {}
+
+impl Movie {
+    /// Starts a recording from the current state of `gameboy`.
+    pub fn from_state(gameboy: &Gameboy) -> Self {
+        Movie {
+            rom_sha1: gameboy.rom_sha1(),
+            start: MovieStart::SaveState(gameboy.save_state()),
+            cheats: gameboy.cheats().to_vec(),
+            cheats_enabled: gameboy.cheats_enabled(),
+            inputs: Vec::new(),
+        }
+    }
+
+    /// Starts a recording of a machine that has just been switched on.
+    pub fn from_power_on(gameboy: &Gameboy) -> Self {
+        Movie {
+            rom_sha1: gameboy.rom_sha1(),
+            start: MovieStart::PowerOn(gameboy.battery().unwrap_or_default()),
+            cheats: gameboy.cheats().to_vec(),
+            cheats_enabled: gameboy.cheats_enabled(),
+            inputs: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, inputs: u8) {
+        self.inputs.push(inputs);
+    }
+
+    /// Puts `gameboy`, which must have the movie's ROM loaded, into the start state.
+    pub fn begin(&self, gameboy: &mut Gameboy) -> Result<(), MovieError> {
+        let actual = gameboy.rom_sha1();
+        if actual != self.rom_sha1 {
+            return Err(MovieError::RomMismatch {
+                expected: self.rom_sha1.clone(),
+                actual,
+            });
+        }
+
+        match &self.start {
+            MovieStart::PowerOn(battery) => {
+                gameboy.power_cycle();
+                gameboy.load_battery(battery);
+            }
+            MovieStart::SaveState(state) => {
+                gameboy.load_state(state).map_err(MovieError::Start)?;
+            }
+        }
+        gameboy.set_cheats(self.cheats.clone(), self.cheats_enabled);
+        Ok(())
+    }
+
+    /// Plays the whole movie, calling `frame` after every emulated frame.
+    pub fn play(
+        &self,
+        gameboy: &mut Gameboy,
+        mut frame: impl FnMut(&Gameboy),
+    ) -> Result<(), MovieError> {
+        self.begin(gameboy)?;
+        for &inputs in &self.inputs {
+            gameboy.write_inputs(inputs);
+            gameboy.run_frame();
+            frame(gameboy);
+        }
+        Ok(())
+    }
+
+    /// Plays the movie twice and compares every frame. Returns the first frame that came
+    /// out differently, or `None` if both runs matched.
+    pub fn check(&self, gameboy: &mut Gameboy) -> Result<Option<usize>, MovieError> {
+        let mut first = Vec::with_capacity(self.inputs.len());
+        self.play(gameboy, |gameboy| first.push(frame_hash(gameboy)))?;
+
+        let mut second = Vec::with_capacity(self.inputs.len());
+        self.play(gameboy, |gameboy| second.push(frame_hash(gameboy)))?;
+
+        Ok(first.iter().zip(&second).position(|(a, b)| a != b))
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut movie = StateWriter::new();
+        for byte in MOVIE_MAGIC {
+            movie.write_u8(byte);
+        }
+        movie.write_u8(MOVIE_VERSION);
+        movie.write_bytes(self.rom_sha1.as_bytes());
+        match &self.start {
+            MovieStart::PowerOn(battery) => {
+                movie.write_u8(0);
+                movie.write_bytes(battery);
+            }
+            MovieStart::SaveState(state) => {
+                movie.write_u8(1);
+                movie.write_bytes(state);
+            }
+        }
+        movie.write_u32(self.cheats.len() as u32);
+        for cheat in &self.cheats {
+            write_cheat(&mut movie, cheat);
+        }
+        movie.write_bool(self.cheats_enabled);
+        movie.write_bytes(&self.inputs);
+        movie.into_bytes()
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, MovieError> {
+        let mut reader = StateReader::new(data);
+        let movie = Movie::read(&mut reader).map_err(MovieError::Format)?;
+        reader.finish().map_err(MovieError::Format)?;
+        Ok(movie)
+    }
+
+    fn read(movie: &mut StateReader) -> Result<Self, StateError> {
+        for byte in MOVIE_MAGIC {
+            if movie.read_u8()? != byte {
+                return Err(StateError::Mismatch("not a movie"));
+            }
+        }
+        if movie.read_u8()? != MOVIE_VERSION {
+            return Err(StateError::Mismatch("version"));
+        }
+        let rom_sha1 =
+            String::from_utf8(movie.read_bytes()?).map_err(|_| StateError::Mismatch("ROM hash"))?;
+        let start = match movie.read_u8()? {
+            0 => MovieStart::PowerOn(movie.read_bytes()?),
+            1 => MovieStart::SaveState(movie.read_bytes()?),
+            _ => return Err(StateError::Mismatch("start")),
+        };
+        let cheats = (0..movie.read_u32()?)
+            .map(|_| read_cheat(movie))
+            .collect::<Result<_, _>>()?;
+        let cheats_enabled = movie.read_bool()?;
+        let inputs = movie.read_bytes()?;
+        Ok(Movie {
+            rom_sha1,
+            start,
+            cheats,
+            cheats_enabled,
+            inputs,
+        })
+    }
+}
+
+fn write_optional(movie: &mut StateWriter, value: Option<u8>) {
+    movie.write_bool(value.is_some());
+    movie.write_u8(value.unwrap_or(0));
+}
+
+fn read_optional(movie: &mut StateReader) -> Result<Option<u8>, StateError> {
+    let present = movie.read_bool()?;
+    let value = movie.read_u8()?;
+    Ok(present.then_some(value))
+}
+
+fn write_cheat(movie: &mut StateWriter, cheat: &Cheat) {
+    match *cheat {
+        Cheat::GameGenie {
+            address,
+            value,
+            compare,
+        } => {
+            movie.write_u8(0);
+            movie.write_u16(address);
+            movie.write_u8(value);
+            write_optional(movie, compare);
+        }
+        Cheat::GameShark {
+            bank,
+            address,
+            value,
+        } => {
+            movie.write_u8(1);
+            movie.write_u16(address);
+            movie.write_u8(value);
+            write_optional(movie, bank);
+        }
+    }
+}
+
+fn read_cheat(movie: &mut StateReader) -> Result<Cheat, StateError> {
+    let kind = movie.read_u8()?;
+    let address = movie.read_u16()?;
+    let value = movie.read_u8()?;
+    let optional = read_optional(movie)?;
+    match kind {
+        0 => Ok(Cheat::GameGenie {
+            address,
+            value,
+            compare: optional,
+        }),
+        1 => Ok(Cheat::GameShark {
+            bank: optional,
+            address,
+            value,
+        }),
+        _ => Err(StateError::Mismatch("cheat")),
+    }
+}
+
+/// SHA-1 over the framebuffer and the whole machine state, memory included.
+pub fn frame_hash(gameboy: &Gameboy) -> String {
+    let mut hash = sha1_smol::Sha1::from(gameboy.framebuffer());
+    hash.update(&gameboy.save_state());
+    hash.digest().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn movie_round_trips_and_replays() {
+        let mut gameboy = Gameboy::new();
+        gameboy.add_cheat("01FF80C0".parse().unwrap());
+        let mut movie = Movie::from_state(&gameboy);
+        for frame in 0..20u8 {
+            let inputs = !(1 << (frame % 8));
+            gameboy.write_inputs(inputs);
+            gameboy.run_frame();
+            movie.push(inputs);
+        }
+        let expected = gameboy.save_state();
+
+        let movie = Movie::from_bytes(&movie.to_bytes()).unwrap();
+        // Playback brings its own cheats, whatever the machine had
+        gameboy.set_cheats(Vec::new(), false);
+        let mut frames = 0;
+        movie.play(&mut gameboy, |_| frames += 1).unwrap();
+        assert_eq!(frames, 20);
+        assert_eq!(gameboy.save_state(), expected);
+        assert_eq!(gameboy.cheats(), movie.cheats);
+        assert!(gameboy.cheats_enabled());
+
+        assert_eq!(movie.check(&mut gameboy).unwrap(), None);
+
+        let mut bytes = movie.to_bytes();
+        bytes.pop();
+        assert!(Movie::from_bytes(&bytes).is_err());
+    }
+}
@@ -0,0 +1,369 @@
+use crate::io::serial_device::SerialDevice;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+const WIDTH: usize = 160;
+/// 20 tiles of 16 bytes make 8 rows of pixels.
+const TILE_ROW_BYTES: usize = 20 * 16;
+/// The printer holds 9 bands of 2 tile rows, a full screen.
+const BUFFER_SIZE: usize = 9 * 2 * TILE_ROW_BYTES;
+/// Rows of paper fed per unit of margin.
+const LINE_FEED_ROWS: usize = 8;
+/// How many status packets report printing after a PRINT.
+const PRINT_POLLS: u8 = 4;
+const SHADES: [u8; 4] = [0xFF, 0xAA, 0x55, 0x00];
+
+const INIT: u8 = 0x01;
+const PRINT: u8 = 0x02;
+const DATA: u8 = 0x04;
+const STATUS: u8 = 0x0F;
+
+const STATUS_CHECKSUM: u8 = 0x01;
+const STATUS_PRINTING: u8 = 0x02;
+const STATUS_FULL: u8 = 0x04;
+const STATUS_UNPROCESSED: u8 = 0x08;
+const STATUS_PACKET_ERROR: u8 = 0x10;
+
+/// Where in a packet the next byte goes.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum State {
+    Magic,
+    Magic2,
+    Command,
+    Compression,
+    LengthLow,
+    LengthHigh,
+    Data,
+    ChecksumLow,
+    ChecksumHigh,
+    Alive,
+    Status,
+}
+
+/// A Game Boy Printer. Packets are `88 33`, command, compression, length, data and a
+/// checksum, after which the printer answers 0x81 and then its status.
+///
+/// Paper comes out as PNGs in `directory`. Prints without a bottom margin end up on the
+/// same sheet as the next one, which is how games print images taller than the buffer.
+pub struct Printer {
+    directory: PathBuf,
+    state: State,
+    command: u8,
+    compressed: bool,
+    length: u16,
+    data: Vec<u8>,
+    checksum: u16,
+    received_checksum: u16,
+    status: u8,
+    print_polls: u8,
+    /// Image data as 2bpp tiles, 20 per row.
+    buffer: Vec<u8>,
+    /// Printed rows of grayscale pixels not yet saved.
+    paper: Vec<u8>,
+}
+
+impl Printer {
+    pub fn new(directory: PathBuf) -> Self {
+        Printer {
+            directory,
+            state: State::Magic,
+            command: 0,
+            compressed: false,
+            length: 0,
+            data: Vec::new(),
+            checksum: 0,
+            received_checksum: 0,
+            status: 0,
+            print_polls: 0,
+            buffer: Vec::new(),
+            paper: Vec::new(),
+        }
+    }
+
+    fn execute(&mut self) {
+        if self.checksum != self.received_checksum {
+            self.status |= STATUS_CHECKSUM;
+            return;
+        }
+        self.status &= !(STATUS_CHECKSUM | STATUS_PACKET_ERROR);
+
+        match self.command {
+            INIT => {
+                self.buffer.clear();
+                self.status = 0;
+                self.print_polls = 0;
+            }
+            DATA => {
+                let data = if self.compressed {
+                    decompress(&self.data)
+                } else {
+                    std::mem::take(&mut self.data)
+                };
+                let room = BUFFER_SIZE - self.buffer.len();
+                self.buffer.extend(data.into_iter().take(room));
+                if !self.buffer.is_empty() {
+                    self.status |= STATUS_UNPROCESSED;
+                }
+                if self.buffer.len() == BUFFER_SIZE {
+                    self.status |= STATUS_FULL;
+                }
+            }
+            PRINT => match self.data[..] {
+                [sheets, margins, palette, _exposure] => {
+                    self.print(sheets, margins, palette);
+                    self.status &= !(STATUS_UNPROCESSED | STATUS_FULL);
+                    self.status |= STATUS_PRINTING;
+                    self.print_polls = PRINT_POLLS;
+                }
+                _ => self.status |= STATUS_PACKET_ERROR,
+            },
+            STATUS => (),
+            _ => self.status |= STATUS_PACKET_ERROR,
+        }
+    }
+
+    /// Margins are in line feeds, before in the upper nibble and after in the lower one.
+    /// No sheets only feeds the paper.
+    fn print(&mut self, sheets: u8, margins: u8, palette: u8) {
+        let before = (margins >> 4) as usize * LINE_FEED_ROWS;
+        let after = (margins & 0x0F) as usize * LINE_FEED_ROWS;
+
+        self.paper
+            .resize(self.paper.len() + before * WIDTH, SHADES[0]);
+        if sheets > 0 {
+            self.paper.extend(render(&self.buffer, palette));
+        }
+        self.buffer.clear();
+        self.paper
+            .resize(self.paper.len() + after * WIDTH, SHADES[0]);
+
+        if after > 0 {
+            self.save_paper();
+        }
+    }
+
+    fn save_paper(&mut self) {
+        if self.paper.is_empty() {
+            return;
+        }
+
+        let path = (1..)
+            .map(|number| self.directory.join(format!("print_{number:03}.png")))
+            .find(|path| !path.exists())
+            .expect("Ran out of file names");
+        match write_png(&path, &self.paper) {
+            Ok(()) => println!("Printed {}", path.display()),
+            Err(error) => eprintln!("Failed to write {}: {error}", path.display()),
+        }
+        self.paper.clear();
+    }
+}
+
+impl SerialDevice for Printer {
+    fn transfer(&mut self, outgoing: u8) -> Option<u8> {
+        let byte = outgoing;
+        let mut reply = 0x00;
+        self.state = match self.state {
+            State::Magic if byte == 0x88 => State::Magic2,
+            State::Magic => State::Magic,
+            State::Magic2 if byte == 0x33 => {
+                self.data.clear();
+                State::Command
+            }
+            State::Magic2 if byte == 0x88 => State::Magic2,
+            State::Magic2 => State::Magic,
+            State::Command => {
+                self.command = byte;
+                self.checksum = byte as u16;
+                State::Compression
+            }
+            State::Compression => {
+                self.compressed = byte & 0x01 != 0;
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                State::LengthLow
+            }
+            State::LengthLow => {
+                self.length = byte as u16;
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                State::LengthHigh
+            }
+            State::LengthHigh => {
+                self.length |= (byte as u16) << 8;
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                if self.length == 0 {
+                    State::ChecksumLow
+                } else {
+                    State::Data
+                }
+            }
+            State::Data => {
+                self.data.push(byte);
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                if self.data.len() == self.length as usize {
+                    State::ChecksumLow
+                } else {
+                    State::Data
+                }
+            }
+            State::ChecksumLow => {
+                self.received_checksum = byte as u16;
+                State::ChecksumHigh
+            }
+            State::ChecksumHigh => {
+                self.received_checksum |= (byte as u16) << 8;
+                State::Alive
+            }
+            State::Alive => {
+                reply = 0x81;
+                self.execute();
+                State::Status
+            }
+            State::Status => {
+                reply = self.status;
+                if self.command == STATUS && self.print_polls > 0 {
+                    self.print_polls -= 1;
+                    if self.print_polls == 0 {
+                        self.status &= !STATUS_PRINTING;
+                    }
+                }
+                State::Magic
+            }
+        };
+        Some(reply)
+    }
+}
+
+impl Drop for Printer {
+    /// Whatever is still in the printer is torn off.
+    fn drop(&mut self) {
+        self.save_paper();
+    }
+}
+
+/// A control byte with bit 7 set repeats the next byte (control & 0x7F) + 2 times,
+/// otherwise the next control + 1 bytes are copied as they are.
+fn decompress(data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::new();
+    let mut bytes = data.iter().copied();
+    while let Some(control) = bytes.next() {
+        if control & 0x80 != 0 {
+            let Some(value) = bytes.next() else {
+                break;
+            };
+            output.extend(std::iter::repeat_n(value, (control & 0x7F) as usize + 2));
+        } else {
+            output.extend(bytes.by_ref().take(control as usize + 1));
+        }
+    }
+    output
+}
+
+/// Turns tile data into grayscale rows. The palette works like BGP; 0x00, which many
+/// games send, stands for the usual 0xE4.
+fn render(buffer: &[u8], palette: u8) -> Vec<u8> {
+    let palette = if palette == 0x00 { 0xE4 } else { palette };
+    let rows = buffer.len() / TILE_ROW_BYTES * 8;
+    let mut pixels = Vec::with_capacity(rows * WIDTH);
+    for y in 0..rows {
+        for x in 0..WIDTH {
+            let offset = ((y / 8) * 20 + x / 8) * 16 + (y % 8) * 2;
+            let bit = 7 - (x % 8);
+            let color = (((buffer[offset + 1] >> bit) & 1) << 1) | ((buffer[offset] >> bit) & 1);
+            pixels.push(SHADES[((palette >> (color * 2)) & 0x03) as usize]);
+        }
+    }
+    pixels
+}
+
+fn write_png(path: &Path, pixels: &[u8]) -> Result<(), png::EncodingError> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(file, WIDTH as u32, (pixels.len() / WIDTH) as u32);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(pixels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sends a whole packet and returns the printer's last two answers.
+    fn send(printer: &mut Printer, command: u8, compressed: bool, data: &[u8]) -> [u8; 2] {
+        let length = (data.len() as u16).to_le_bytes();
+        let mut packet = vec![0x88, 0x33, command, compressed as u8, length[0], length[1]];
+        packet.extend_from_slice(data);
+        let checksum = packet[2..].iter().map(|&byte| byte as u16).sum::<u16>();
+        packet.extend_from_slice(&checksum.to_le_bytes());
+        packet.extend_from_slice(&[0x00, 0x00]);
+
+        let replies: Vec<u8> = packet
+            .into_iter()
+            .map(|byte| printer.transfer(byte).unwrap())
+            .collect();
+        [replies[replies.len() - 2], replies[replies.len() - 1]]
+    }
+
+    #[test]
+    fn prints_compressed_data_to_png() {
+        let directory =
+            std::env::temp_dir().join(format!("gameboy-printer-{}", std::process::id()));
+        let mut printer = Printer::new(directory.clone());
+        assert_eq!(send(&mut printer, INIT, false, &[]), [0x81, 0x00]);
+
+        // A black tile row as runs of 0xFF, then a white one as literals
+        let mut data = vec![0xFE, 0xFF, 0xFE, 0xFF, 0xBE, 0xFF];
+        for count in [128, 128, 64] {
+            data.push(count - 1);
+            data.extend(std::iter::repeat_n(0x00, count as usize));
+        }
+        assert_eq!(
+            send(&mut printer, DATA, true, &data),
+            [0x81, STATUS_UNPROCESSED]
+        );
+        assert_eq!(
+            send(&mut printer, DATA, false, &[]),
+            [0x81, STATUS_UNPROCESSED]
+        );
+
+        assert_eq!(
+            send(&mut printer, PRINT, false, &[1, 0x01, 0xE4, 0x40]),
+            [0x81, STATUS_PRINTING]
+        );
+        for _ in 1..PRINT_POLLS {
+            assert_eq!(
+                send(&mut printer, STATUS, false, &[]),
+                [0x81, STATUS_PRINTING]
+            );
+        }
+        assert_eq!(
+            send(&mut printer, STATUS, false, &[]),
+            [0x81, STATUS_PRINTING]
+        );
+        assert_eq!(send(&mut printer, STATUS, false, &[]), [0x81, 0x00]);
+
+        let decoder = png::Decoder::new(File::open(directory.join("print_001.png")).unwrap());
+        let mut reader = decoder.read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        reader.next_frame(&mut pixels).unwrap();
+        assert_eq!(reader.info().height, 16 + LINE_FEED_ROWS as u32);
+        assert!(pixels[..8 * WIDTH].iter().all(|&pixel| pixel == 0x00));
+        assert!(pixels[8 * WIDTH..].iter().all(|&pixel| pixel == 0xFF));
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn bad_checksums_are_reported() {
+        let mut printer = Printer::new(PathBuf::new());
+        let packet = [0x88, 0x33, INIT, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00];
+        let replies: Vec<u8> = packet
+            .into_iter()
+            .map(|byte| printer.transfer(byte).unwrap())
+            .collect();
+        assert_eq!(replies[8..], [0x81, STATUS_CHECKSUM]);
+        assert_eq!(send(&mut printer, STATUS, false, &[]), [0x81, 0x00]);
+    }
+}
@@ -0,0 +1,141 @@
+use std::error::Error;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Where the save state hotkeys keep their single slot, next to the ROM.
+pub fn state_path(rom_path: &Path) -> PathBuf {
+    rom_path.with_extension("state")
+}
+
+#[derive(Debug)]
+pub enum StateError {
+    /// The state ended before every component was restored.
+    Truncated,
+    /// The state does not belong to this emulator or this cartridge.
+    Mismatch(&'static str),
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateError::Truncated => write!(f, "Save state is truncated"),
+            StateError::Mismatch(what) => write!(f, "Save state does not match: {}", what),
+        }
+    }
+}
+
+impl Error for StateError {}
+
+/// Little-endian serializer for save states. Components write their fields in a fixed
+/// order and read them back in the same order.
+pub struct StateWriter {
+    data: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        StateWriter { data: Vec::new() }
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.data.push(value as u8);
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_i32(&mut self, value: i32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    /// Writes a length-prefixed block, e.g. cartridge RAM.
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.write_u32(bytes.len() as u32);
+        self.data.extend_from_slice(bytes);
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        StateReader { data, position: 0 }
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [u8], StateError> {
+        let bytes = self
+            .data
+            .get(self.position..self.position + length)
+            .ok_or(StateError::Truncated)?;
+        self.position += length;
+        Ok(bytes)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, StateError> {
+        Ok(self.read_u8()? != 0)
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, StateError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, StateError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn read_i32(&mut self) -> Result<i32, StateError> {
+        Ok(i32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, StateError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    /// Reads a block written by `write_bytes` into a buffer that must have the same length.
+    pub fn read_bytes_into(&mut self, buffer: &mut [u8]) -> Result<(), StateError> {
+        if self.read_u32()? as usize != buffer.len() {
+            return Err(StateError::Mismatch("block size"));
+        }
+        buffer.copy_from_slice(self.take(buffer.len())?);
+        Ok(())
+    }
+
+    /// Reads a block written by `write_bytes` whose length isn't known up front.
+    pub fn read_bytes(&mut self) -> Result<Vec<u8>, StateError> {
+        let length = self.read_u32()? as usize;
+        Ok(self.take(length)?.to_vec())
+    }
+
+    /// Fails if anything is left over, which means the layout did not match.
+    pub fn finish(self) -> Result<(), StateError> {
+        if self.position == self.data.len() {
+            Ok(())
+        } else {
+            Err(StateError::Mismatch("trailing data"))
+        }
+    }
+}
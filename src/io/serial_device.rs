@@ -0,0 +1,17 @@
+use std::any::Any;
+
+/// Something plugged into the link port. Whole bytes are exchanged with it: one goes
+/// out while the other comes in.
+pub trait SerialDevice: Any + Send {
+    /// The Game Boy shifted out `outgoing` with its internal clock; returns the byte
+    /// shifted in at the same time, or `None` if it comes later through `poll`.
+    fn transfer(&mut self, outgoing: u8) -> Option<u8>;
+
+    /// Called every step with what is in SB while the Game Boy waits for an external
+    /// clock or for the byte of a transfer that returned `None`. Devices that drive the
+    /// clock themselves return the byte they shifted in once they did, holding on to
+    /// it until then if SC wasn't armed yet.
+    fn poll(&mut self, _outgoing: u8) -> Option<u8> {
+        None
+    }
+}
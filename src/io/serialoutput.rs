@@ -1,3 +1,7 @@
+use crate::io::serial_device::SerialDevice;
+
+/// What is plugged in when nothing is: logs every byte sent, which is how test ROMs
+/// report their results, and reads 0xFF back.
 pub struct SerialOutput {
     buffer: Vec<u8>,
 }
@@ -15,3 +19,11 @@ impl SerialOutput {
         String::from_utf8_lossy(&self.buffer).to_string()
     }
 }
+
+impl SerialDevice for SerialOutput {
+    fn transfer(&mut self, outgoing: u8) -> Option<u8> {
+        self.write_byte(outgoing);
+        print!("{}", outgoing as char);
+        Some(0xFF)
+    }
+}
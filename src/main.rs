@@ -1,15 +1,108 @@
+mod cli;
 mod components;
 mod io;
 mod utils;
 mod window;
 
-use crate::window::emulator_app::{EmulatorApp, HEIGHT, WIDTH};
+use crate::io::camera_source;
+use crate::io::link::TcpLink;
+use crate::io::printer::Printer;
+use crate::window::emulator_app::{EmulatorApp, EmulatorOptions, HEIGHT, WIDTH};
+use crate::window::speed::SyncMode;
+use std::path::Path;
 use std::sync::Arc;
 use winit::event::{Event, WindowEvent};
 use winit::event_loop::{ControlFlow, EventLoop};
 use winit::window::WindowBuilder;
 
+const DEFAULT_ROM: &str = "resources/roms/ppu/dmg-acid2.gb";
+const USAGE: &str = "Usage: gameboy [--turbo <multiplier>] [--sync video|audio] [--record <movie>]\n                [--link-listen <port> | --link-connect <port> | --printer <dir>]\n                [--camera <png or directory>] [rom]\n       gameboy info [--json] <rom...>\n       gameboy play [--check] <rom> <movie> [--link <rom> <movie>]\n                    [--camera <png or directory>] [--expect <frame sha1>]...";
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("info") => std::process::exit(cli::info::run(&args[1..])),
+        Some("play") => std::process::exit(cli::play::run(&args[1..])),
+        _ => (),
+    }
+
+    let mut rom_path = DEFAULT_ROM;
+    let mut options = EmulatorOptions::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--turbo" => {
+                options.turbo_multiplier = match args.next().and_then(|value| value.parse().ok()) {
+                    Some(multiplier) if multiplier > 0.0 => multiplier,
+                    _ => {
+                        eprintln!("{USAGE}");
+                        std::process::exit(2);
+                    }
+                }
+            }
+            "--sync" => {
+                options.sync = match args.next().map(String::as_str) {
+                    Some("video") => SyncMode::Video,
+                    Some("audio") => SyncMode::Audio,
+                    _ => {
+                        eprintln!("{USAGE}");
+                        std::process::exit(2);
+                    }
+                }
+            }
+            "--record" => match args.next() {
+                Some(path) => options.record = Some(path.into()),
+                None => {
+                    eprintln!("{USAGE}");
+                    std::process::exit(2);
+                }
+            },
+            "--link-listen" | "--link-connect" => {
+                let Some(port) = args.next().and_then(|value| value.parse().ok()) else {
+                    eprintln!("{USAGE}");
+                    std::process::exit(2);
+                };
+                let link = if arg == "--link-listen" {
+                    TcpLink::listen(port)
+                } else {
+                    TcpLink::connect(port)
+                };
+                match link {
+                    Ok(link) => options.serial = Some(Box::new(link)),
+                    Err(error) => {
+                        eprintln!("Failed to set up the link cable on port {port}: {error}");
+                        std::process::exit(1);
+                    }
+                }
+            }
+            "--camera" => {
+                let Some(path) = args.next() else {
+                    eprintln!("{USAGE}");
+                    std::process::exit(2);
+                };
+                match camera_source::load(Path::new(path)) {
+                    Ok(source) => options.camera = Some(source),
+                    Err(error) => {
+                        eprintln!("Failed to load camera pictures from {path}: {error}");
+                        std::process::exit(1);
+                    }
+                }
+            }
+            "--printer" => match args.next() {
+                Some(directory) => options.serial = Some(Box::new(Printer::new(directory.into()))),
+                None => {
+                    eprintln!("{USAGE}");
+                    std::process::exit(2);
+                }
+            },
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return Ok(());
+            }
+            _ => rom_path = arg,
+        }
+    }
+
     let event_loop = EventLoop::new()?;
     let window = Arc::new(
         WindowBuilder::new()
@@ -18,7 +111,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             .build(&event_loop)?,
     );
 
-    let mut emulator_app = EmulatorApp::new(&window, "resources/roms/ppu/dmg-acid2.gb");
+    let mut emulator_app = EmulatorApp::new(&window, rom_path, options);
 
     let window_clone = Arc::clone(&window);
     event_loop.run(move |event, elwt| {
@@ -26,11 +119,20 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
         match event {
             Event::WindowEvent { event, .. } => match event {
-                WindowEvent::CloseRequested => elwt.exit(),
+                WindowEvent::CloseRequested => {
+                    emulator_app.shutdown();
+                    elwt.exit();
+                }
+                WindowEvent::DroppedFile(path) => emulator_app.load_rom(&path),
                 WindowEvent::KeyboardInput { event, .. } => {
                     let keycode = event.physical_key;
                     let pressed = event.state;
                     emulator_app.update_inputs(keycode, pressed);
+                    emulator_app.handle_hotkey(keycode, pressed, event.repeat);
+                }
+                WindowEvent::CursorMoved { position, .. } => emulator_app.cursor_moved(position),
+                WindowEvent::MouseInput { state, button, .. } => {
+                    emulator_app.mouse_input(button, state)
                 }
                 WindowEvent::RedrawRequested => {
                     emulator_app.update();
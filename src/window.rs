@@ -1 +1,6 @@
+pub mod audio;
+pub mod console;
 pub mod emulator_app;
+pub mod keymap;
+pub mod rewind;
+pub mod speed;
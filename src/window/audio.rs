@@ -0,0 +1,174 @@
+use crate::components::apu::SAMPLE_RATE;
+use rodio::Source;
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+/// Audio latency the queue is kept around, in samples (60 ms).
+pub const TARGET_LEVEL: usize = SAMPLE_RATE as usize * 60 / 1000;
+/// Beyond this the queue drops new samples instead of adding latency.
+const MAX_LEVEL: usize = TARGET_LEVEL * 4;
+/// How far dynamic rate control may move the output rate from nominal.
+const MAX_RATE_DEVIATION: f64 = 0.005;
+/// Samples the output side takes out of the queue at a time.
+const CHUNK: usize = 256;
+
+/// Samples waiting to be played, shared between the emulation thread and the output
+/// stream so the emulation side always knows how much audio is buffered.
+pub struct AudioQueue {
+    samples: Mutex<VecDeque<f32>>,
+    drained: Condvar,
+}
+
+impl AudioQueue {
+    pub fn new() -> Arc<Self> {
+        Arc::new(AudioQueue {
+            samples: Mutex::new(VecDeque::new()),
+            drained: Condvar::new(),
+        })
+    }
+
+    pub fn push(&self, samples: &[f32]) {
+        let mut queue = self.samples.lock().unwrap();
+        let room = MAX_LEVEL.saturating_sub(queue.len());
+        queue.extend(samples.iter().take(room));
+    }
+
+    pub fn level(&self) -> usize {
+        self.samples.lock().unwrap().len()
+    }
+
+    /// Blocks until at most `level` samples are queued, or `timeout` has passed.
+    pub fn wait_below(&self, level: usize, timeout: Duration) {
+        let queue = self.samples.lock().unwrap();
+        let _ = self
+            .drained
+            .wait_timeout_while(queue, timeout, |queue| queue.len() > level)
+            .unwrap();
+    }
+
+    fn take(&self, out: &mut Vec<f32>) {
+        let mut queue = self.samples.lock().unwrap();
+        let count = queue.len().min(CHUNK);
+        out.extend(queue.drain(..count));
+        self.drained.notify_all();
+    }
+}
+
+/// Output rate for the APU's blip buffer that steers the queue towards `TARGET_LEVEL`:
+/// a little faster when it runs low, a little slower when it fills up.
+pub fn adjusted_rate(level: usize) -> f64 {
+    let error = (TARGET_LEVEL as f64 - level as f64) / TARGET_LEVEL as f64;
+    SAMPLE_RATE as f64 * (1.0 + MAX_RATE_DEVIATION * error.clamp(-1.0, 1.0))
+}
+
+/// Endless rodio source playing whatever is in the queue, silence on underrun. It ends
+/// once the emulation side has dropped its handle.
+pub struct QueueSource {
+    queue: Arc<AudioQueue>,
+    buffer: Vec<f32>,
+    position: usize,
+}
+
+impl QueueSource {
+    pub fn new(queue: Arc<AudioQueue>) -> Self {
+        QueueSource {
+            queue,
+            buffer: Vec::with_capacity(CHUNK),
+            position: 0,
+        }
+    }
+}
+
+impl Iterator for QueueSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.position == self.buffer.len() {
+            if Arc::strong_count(&self.queue) == 1 {
+                return None;
+            }
+            self.buffer.clear();
+            self.position = 0;
+            self.queue.take(&mut self.buffer);
+            if self.buffer.is_empty() {
+                return Some(0.0);
+            }
+        }
+        self.position += 1;
+        Some(self.buffer[self.position - 1])
+    }
+}
+
+impl Source for QueueSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// Audio that was generated at `speed` times real time, squeezed back to real time by
+/// averaging every `speed` input samples into one. Pitch rises with speed, but the
+/// output queue no longer grows.
+pub fn resample(samples: &[i16], speed: f64) -> Vec<f32> {
+    if speed <= 1.0 {
+        return samples.iter().map(|s| *s as f32 / 32768.0).collect();
+    }
+
+    let output_len = (samples.len() as f64 / speed) as usize;
+    (0..output_len)
+        .map(|i| {
+            let start = (i as f64 * speed) as usize;
+            let end = (((i + 1) as f64 * speed) as usize).min(samples.len());
+            let window = &samples[start..end.max(start + 1)];
+            let sum: i32 = window.iter().map(|s| *s as i32).sum();
+            sum as f32 / window.len() as f32 / 32768.0
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rate_control_steers_towards_target() {
+        assert_eq!(adjusted_rate(TARGET_LEVEL), SAMPLE_RATE as f64);
+        assert!(adjusted_rate(0) > SAMPLE_RATE as f64);
+        assert!(adjusted_rate(TARGET_LEVEL * 3) < SAMPLE_RATE as f64);
+        assert!(adjusted_rate(usize::MAX / 2) >= SAMPLE_RATE as f64 * (1.0 - MAX_RATE_DEVIATION));
+    }
+
+    #[test]
+    fn queue_source_plays_silence_on_underrun() {
+        let queue = AudioQueue::new();
+        let mut source = QueueSource::new(Arc::clone(&queue));
+        queue.push(&[0.25, 0.5]);
+        assert_eq!(source.next(), Some(0.25));
+        assert_eq!(source.next(), Some(0.5));
+        assert_eq!(source.next(), Some(0.0));
+        assert_eq!(queue.level(), 0);
+        drop(queue);
+        assert_eq!(source.next(), None);
+    }
+
+    #[test]
+    fn resample_keeps_real_time_length() {
+        let samples = vec![16384i16; 2000];
+        assert_eq!(resample(&samples, 1.0).len(), 2000);
+        let faster = resample(&samples, 4.0);
+        assert_eq!(faster.len(), 500);
+        assert!(faster.iter().all(|s| (*s - 0.5).abs() < f32::EPSILON));
+    }
+}
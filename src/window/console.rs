@@ -0,0 +1,122 @@
+use crate::components::gameboy::Gameboy;
+use crate::components::ram_search::{Filter, RamSearch, ValueType, Watches};
+
+/// How many search candidates `results` prints at most.
+const MAX_RESULTS: usize = 32;
+
+const HELP: &str = "\
+search [8|16le|16be]      start a new RAM search
+filter equal|changed|increased|decreased|value <n>
+results                   list the remaining candidates
+watch <address> [8|16le|16be]
+unwatch <address>
+log                       every change seen by the watches
+peek <address>
+poke <address> <value>
+trace                     print every bus access after each instruction, or stop";
+
+/// Debug commands typed into the terminal, run on the emulation thread between frames.
+#[derive(Default)]
+pub struct Console {
+    search: Option<RamSearch>,
+    watches: Watches,
+}
+
+impl Console {
+    pub fn execute(&mut self, line: &str, gameboy: &mut Gameboy) -> Result<(), String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words[..] {
+            [] => (),
+            ["help"] => println!("{HELP}"),
+            ["search"] | ["search", _] => {
+                let value_type = words
+                    .get(1)
+                    .map_or(Ok(ValueType::U8), |name| name.parse())?;
+                let search = RamSearch::new(gameboy, value_type);
+                println!("{} candidates", search.candidates().len());
+                self.search = Some(search);
+            }
+            ["filter", name, ref value @ ..] => {
+                let filter = match (name, value) {
+                    ("equal", []) => Filter::Equal,
+                    ("changed", []) => Filter::Changed,
+                    ("increased", []) => Filter::Increased,
+                    ("decreased", []) => Filter::Decreased,
+                    ("value", [value]) => Filter::Value(parse_value(value)?),
+                    _ => return Err(format!("Unknown filter {line}")),
+                };
+                let search = self.search.as_mut().ok_or("No search running")?;
+                search.filter(gameboy, filter);
+                println!("{} candidates", search.candidates().len());
+            }
+            ["results"] => {
+                let search = self.search.as_ref().ok_or("No search running")?;
+                for candidate in search.candidates().iter().take(MAX_RESULTS) {
+                    println!("{:04X}: {}", candidate.address, candidate.value);
+                }
+                if search.candidates().len() > MAX_RESULTS {
+                    println!("...and {} more", search.candidates().len() - MAX_RESULTS);
+                }
+            }
+            ["watch", address] | ["watch", address, _] => {
+                let value_type = words
+                    .get(2)
+                    .map_or(Ok(ValueType::U8), |name| name.parse())?;
+                self.watches
+                    .add(gameboy, parse_address(address)?, value_type);
+            }
+            ["unwatch", address] => {
+                if !self.watches.remove(parse_address(address)?) {
+                    return Err(format!("{address} is not watched"));
+                }
+            }
+            ["log"] => {
+                for change in self.watches.log() {
+                    println!(
+                        "Frame {}: {:04X} {} -> {}",
+                        change.frame, change.address, change.old, change.new
+                    );
+                }
+            }
+            ["peek", address] => {
+                let address = parse_address(address)?;
+                println!("{address:04X}: {:02X}", gameboy.peek(address));
+            }
+            ["poke", address, value] => {
+                let value = u8::try_from(parse_value(value)?).map_err(|error| error.to_string())?;
+                gameboy.poke(parse_address(address)?, value);
+            }
+            ["trace"] => {
+                let tracing = gameboy.toggle_bus_trace();
+                println!("Bus trace {}", if tracing { "on" } else { "off" });
+            }
+            _ => return Err(format!("Unknown command {line}, try help")),
+        }
+        Ok(())
+    }
+
+    /// Called after every frame; prints what the watches saw change.
+    pub fn frame(&mut self, frame: u64, gameboy: &Gameboy) {
+        for change in self.watches.update(frame, gameboy) {
+            println!(
+                "Frame {}: {:04X} {} -> {}",
+                change.frame, change.address, change.old, change.new
+            );
+        }
+    }
+}
+
+/// Addresses are always hex, with or without a `0x` or `$` prefix.
+fn parse_address(text: &str) -> Result<u16, String> {
+    let digits = text.trim_start_matches("0x").trim_start_matches('$');
+    u16::from_str_radix(digits, 16).map_err(|_| format!("Invalid address {text}"))
+}
+
+/// Values are decimal unless prefixed with `0x` or `$`.
+fn parse_value(text: &str) -> Result<u16, String> {
+    let value = match text.strip_prefix("0x").or_else(|| text.strip_prefix('$')) {
+        Some(digits) => u16::from_str_radix(digits, 16),
+        None => text.parse(),
+    };
+    value.map_err(|_| format!("Invalid value {text}"))
+}
@@ -1,100 +1,411 @@
+use crate::components::cartridge::{CameraSource, CartridgeError};
 use crate::components::gameboy::Gameboy;
+use crate::io::serial_device::SerialDevice;
+use crate::io::movie::Movie;
+use crate::io::{battery, cheat_file, savestate};
+use crate::window::audio::{AudioQueue, QueueSource, TARGET_LEVEL, adjusted_rate, resample};
+use crate::window::console::Console;
+use crate::window::keymap::{Action, Keymap};
+use crate::window::rewind::RewindBuffer;
+use crate::window::speed::{FRAME_RATE, FrameLimiter, Speed, SyncMode};
 use pixels::{Pixels, SurfaceTexture};
 use rodio::OutputStream;
-use std::sync::mpsc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, mpsc};
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::sync::mpsc::{Receiver, Sender};
-use std::thread;
+use std::thread::{self, JoinHandle};
 use std::time::{Duration, Instant};
-use winit::event::ElementState;
-use winit::keyboard::{KeyCode, PhysicalKey};
+use winit::dpi::PhysicalPosition;
+use winit::event::{ElementState, MouseButton};
+use winit::keyboard::PhysicalKey;
 use winit::window::Window;
 
 pub const WIDTH: u32 = 160;
 pub const HEIGHT: u32 = 144;
 
+/// A snapshot is taken every this many frames while playing.
+const REWIND_INTERVAL: u64 = 4;
+const REWIND_BUDGET: usize = 64 * 1024 * 1024;
+/// Audio sync falls back to the frame limiter when no samples came for this many frames.
+const AUDIO_IDLE_FRAMES: u32 = 8;
+/// How far the tilt keys tip the Game Boy, in g.
+const KEY_TILT: f32 = 0.5;
+
+pub struct EmulatorOptions {
+    pub turbo_multiplier: f64,
+    pub sync: SyncMode,
+    /// Records a movie of the first ROM from power-on into this file.
+    pub record: Option<PathBuf>,
+    /// Pictures for a Pocket Camera, used for every ROM loaded.
+    pub camera: Option<CameraSource>,
+    /// What is plugged into the link port, kept plugged in when another ROM is loaded.
+    pub serial: Option<Box<dyn SerialDevice>>,
+}
+
+impl Default for EmulatorOptions {
+    fn default() -> Self {
+        EmulatorOptions {
+            turbo_multiplier: 3.0,
+            sync: SyncMode::Video,
+            record: None,
+            camera: None,
+            serial: None,
+        }
+    }
+}
+
+/// Requests from the window thread that change the state of the machine.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Command {
+    TogglePause,
+    /// Pauses, then runs exactly one frame.
+    FrameAdvance,
+    SoftReset,
+    PowerCycle,
+    SaveState,
+    LoadState,
+    /// Starts recording a movie from the current state, or stops and saves it.
+    ToggleRecording,
+    ToggleCheats,
+    /// Tilt for cartridges with an accelerometer, in g.
+    Tilt(f32, f32),
+    /// A line typed into the debug console.
+    Console(String),
+    /// Writes battery RAM back to disk and ends the emulation thread.
+    Shutdown,
+}
+
 pub struct EmulatorApp<'a> {
     pixels: Pixels<'a>,
     rx_pixels: Receiver<Vec<u8>>,
     tx_inputs: Sender<u8>,
-    _window: &'a Window,
+    tx_control: Sender<Command>,
+    rx_console: Receiver<String>,
+    emulation: Option<JoinHandle<Box<dyn SerialDevice>>>,
+    rewinding: Arc<AtomicBool>,
+    speed: Arc<Speed>,
+    options: EmulatorOptions,
+    keymap: Keymap,
+    window: &'a Window,
     input_buffer: u8,
+    /// Tilt keys held, as (left, right, up, down).
+    tilt_keys: [bool; 4],
+    /// Cursor position, as a fraction of the window from -1 to 1 on both axes.
+    cursor: (f32, f32),
+    /// While the left mouse button is held, the cursor tilts instead of the keys.
+    mouse_tilt: bool,
 }
 
 impl<'a> EmulatorApp<'a> {
-    pub(crate) fn new(window: &'a Window, rom_path: &str) -> Self {
-        let mut gameboy = Gameboy::new();
-        gameboy.cartridge_to_rom(rom_path.to_string());
-        //gameboy.toggle_debug_registers();
+    pub(crate) fn new(window: &'a Window, rom_path: &str, options: EmulatorOptions) -> Self {
+        let mut gameboy = load_gameboy(Path::new(rom_path)).expect("Failed to load ROM");
+        load_battery(&mut gameboy, Path::new(rom_path));
 
         let (tx_pixels, rx_pixels) = mpsc::channel();
         let (tx_inputs, rx_inputs) = mpsc::channel();
-        let (tx_audio, rx_audio) = mpsc::channel();
-        
+        let (tx_control, rx_control) = mpsc::channel();
+        let (tx_console, rx_console) = mpsc::channel();
         thread::spawn(move || {
-            let (_stream, stream_handle) = OutputStream::try_default().unwrap();
-            let sink = rodio::Sink::try_new(&stream_handle).unwrap();
-            
-            while let Ok(samples) = rx_audio.recv() {
-                sink.append(rodio::buffer::SamplesBuffer::new(1, 44100, samples));
+            for line in std::io::stdin().lines().map_while(Result::ok) {
+                if tx_console.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        let surface_texture = SurfaceTexture::new(WIDTH, HEIGHT, window);
+        let pixels =
+            Pixels::new(WIDTH, HEIGHT, surface_texture).expect("Failed to create pixels context");
+
+        let mut app = Self {
+            pixels,
+            rx_pixels,
+            tx_inputs,
+            tx_control,
+            rx_console,
+            emulation: None,
+            rewinding: Arc::new(AtomicBool::new(false)),
+            speed: Arc::new(Speed::new(options.turbo_multiplier)),
+            options,
+            keymap: Keymap::default(),
+            window,
+            input_buffer: 0xFF,
+            tilt_keys: [false; 4],
+            cursor: (0.0, 0.0),
+            mouse_tilt: false,
+        };
+        app.start(gameboy, rom_path.into(), tx_pixels, rx_inputs, rx_control);
+        app
+    }
+
+    /// Replaces the running game with the ROM at `rom_path`. A ROM that fails to load
+    /// leaves the current game running.
+    pub(crate) fn load_rom(&mut self, rom_path: &Path) {
+        let is_rom = rom_path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| matches!(extension.to_ascii_lowercase().as_str(), "gb" | "gbc"));
+        if !is_rom {
+            eprintln!("Not a Game Boy ROM: {}", rom_path.display());
+            return;
+        }
+
+        let mut gameboy = match load_gameboy(rom_path) {
+            Ok(gameboy) => gameboy,
+            Err(error) => {
+                eprintln!("Failed to load {}: {error}", rom_path.display());
+                return;
+            }
+        };
+
+        // The running game may be this one, so its battery RAM has to be flushed before
+        // the save is read
+        self.shutdown();
+        load_battery(&mut gameboy, rom_path);
+        let (tx_pixels, rx_pixels) = mpsc::channel();
+        let (tx_inputs, rx_inputs) = mpsc::channel();
+        let (tx_control, rx_control) = mpsc::channel();
+        self.rx_pixels = rx_pixels;
+        self.tx_inputs = tx_inputs;
+        self.tx_control = tx_control;
+        self.input_buffer = 0xFF;
+        self.start(gameboy, rom_path.to_path_buf(), tx_pixels, rx_inputs, rx_control);
+    }
+
+    /// Stops the emulation thread after it has flushed battery RAM.
+    pub(crate) fn shutdown(&mut self) {
+        if let Some(emulation) = self.emulation.take() {
+            // The thread may already be gone, in which case there is nothing to flush
+            let _ = self.tx_control.send(Command::Shutdown);
+            match emulation.join() {
+                Ok(device) => self.options.serial = Some(device),
+                Err(_) => eprintln!("Emulation thread panicked"),
+            }
+        }
+    }
+
+    fn start(
+        &mut self,
+        mut gameboy: Gameboy,
+        rom_path: PathBuf,
+        tx_pixels: Sender<Vec<u8>>,
+        rx_inputs: Receiver<u8>,
+        rx_control: Receiver<Command>,
+    ) {
+        let title = gameboy.header().map_or(String::new(), |header| header.title.clone());
+        self.window.set_title(&format!("Gameboy Emulator - {title}"));
+        self.keymap = Keymap::load(&title, &gameboy.rom_sha1()).unwrap_or_else(|error| {
+            eprintln!("{error}\nUsing the default key bindings");
+            Keymap::default()
+        });
+        //gameboy.toggle_debug_registers();
+
+        let sync = self.options.sync;
+        let mut recording = self
+            .options
+            .record
+            .take()
+            .map(|path| (path, Movie::from_power_on(&gameboy)));
+        if let Some(source) = &self.options.camera {
+            gameboy.set_camera_source(source.clone());
+        }
+        if let Some(device) = self.options.serial.take() {
+            gameboy.connect_serial(device);
+        }
+        // Games pulse the motor to vary its strength, so only whole frames with or
+        // without rumble get logged
+        let motor_on = Arc::new(AtomicBool::new(false));
+        let rumbled = Arc::new(AtomicBool::new(false));
+        let (callback_motor_on, callback_rumbled) = (Arc::clone(&motor_on), Arc::clone(&rumbled));
+        gameboy.set_rumble_callback(move |on| {
+            callback_motor_on.store(on, Ordering::Relaxed);
+            if on {
+                callback_rumbled.store(true, Ordering::Relaxed);
             }
         });
+        let audio = AudioQueue::new();
+        let output_queue = Arc::clone(&audio);
+        let rewind_held = Arc::clone(&self.rewinding);
+        let emulation_speed = Arc::clone(&self.speed);
 
         thread::spawn(move || {
-            let frame_duration = Duration::from_secs_f64(1.0 / 60.0);
-            let cycles_per_frame = 69904;
+            let (_stream, stream_handle) = OutputStream::try_default().unwrap();
+            let sink = rodio::Sink::try_new(&stream_handle).unwrap();
+            sink.append(QueueSource::new(output_queue));
+            sink.sleep_until_end();
+        });
 
-            loop {
-                let start_time = Instant::now();
+        self.emulation = Some(thread::spawn(move || {
+            let display_period = Duration::from_secs_f64(1.0 / FRAME_RATE);
+            let mut limiter = FrameLimiter::new();
+            let mut last_presented = Instant::now();
+            let mut rewind = RewindBuffer::new(REWIND_BUDGET);
+            let mut frame: u64 = 0;
+            let mut frames_without_audio = AUDIO_IDLE_FRAMES;
+            let mut paused = false;
+            let mut console = Console::default();
+            let mut held = 0xFF;
+            let mut tapped = 0xFF;
+            let mut rumbling = false;
 
-                if let Ok(inputs) = rx_inputs.try_recv() {
-                    gameboy.write_inputs(inputs);
+            'emulation: loop {
+                for inputs in rx_inputs.try_iter() {
+                    held = inputs;
+                    tapped &= inputs;
                 }
 
-                while gameboy.cycles < cycles_per_frame {
-                    gameboy.execute_cycle();
+                let mut step = false;
+                for command in rx_control.try_iter() {
+                    match command {
+                        Command::TogglePause => paused = !paused,
+                        Command::FrameAdvance => {
+                            paused = true;
+                            step = true;
+                        }
+                        Command::SoftReset => {
+                            finish_recording(&mut recording);
+                            gameboy.reset();
+                            rewind.clear();
+                        }
+                        Command::PowerCycle => {
+                            finish_recording(&mut recording);
+                            gameboy.power_cycle();
+                            rewind.clear();
+                        }
+                        Command::SaveState => {
+                            let path = savestate::state_path(&rom_path);
+                            if let Err(error) = fs::write(&path, gameboy.save_state()) {
+                                eprintln!("Failed to write {}: {error}", path.display());
+                            }
+                        }
+                        Command::LoadState => {
+                            finish_recording(&mut recording);
+                            let path = savestate::state_path(&rom_path);
+                            match fs::read(&path) {
+                                Ok(state) => {
+                                    if let Err(error) = gameboy.load_state(&state) {
+                                        eprintln!("Failed to load {}: {error}", path.display());
+                                    }
+                                }
+                                Err(error) => eprintln!("Failed to read {}: {error}", path.display()),
+                            }
+                        }
+                        Command::ToggleRecording => {
+                            if recording.is_some() {
+                                finish_recording(&mut recording);
+                            } else {
+                                println!("Recording movie");
+                                recording = Some((movie_path(&rom_path), Movie::from_state(&gameboy)));
+                            }
+                        }
+                        Command::ToggleCheats => {
+                            // The movie only knows the cheats it started with
+                            finish_recording(&mut recording);
+                            let enabled = gameboy.toggle_cheats();
+                            println!("Cheats {}", if enabled { "on" } else { "off" });
+                        }
+                        Command::Tilt(x, y) => gameboy.set_tilt(x, y),
+                        Command::Console(line) => {
+                            if let Err(error) = console.execute(&line, &mut gameboy) {
+                                eprintln!("{error}");
+                            }
+                        }
+                        Command::Shutdown => break 'emulation,
+                    }
+                }
+                if paused && !step {
+                    thread::sleep(display_period);
+                    limiter = FrameLimiter::new();
+                    continue;
                 }
-                gameboy.cycles = 0;
 
-                let mut pixels = vec![0; (WIDTH * HEIGHT * 4) as usize];
-                gameboy.ppu.copy_to_framebuffer(&mut pixels);
+                let rewinding = rewind_held.load(Ordering::Relaxed);
+                if rewinding {
+                    // A movie only goes forward
+                    finish_recording(&mut recording);
+                    // Steps back one snapshot per frame; the oldest one stays on screen
+                    if let Some(state) = rewind.pop() {
+                        gameboy.load_state(&state).expect("Failed to restore rewind state");
+                    }
+                } else {
+                    // Inputs only change between frames. A button tapped since the last
+                    // frame counts as held for this one so short presses aren't lost.
+                    let inputs = held & tapped;
+                    tapped = 0xFF;
+                    gameboy.write_inputs(inputs);
+                    gameboy.run_frame();
+                    if let Some((_, movie)) = &mut recording {
+                        movie.push(inputs);
+                    }
 
-                if tx_pixels.send(pixels).is_err() {
-                    break;
+                    if frame.is_multiple_of(REWIND_INTERVAL) {
+                        rewind.push(gameboy.save_state());
+                    }
+                    console.frame(frame, &gameboy);
+                    frame += 1;
+
+                    let rumbled = rumbled.swap(motor_on.load(Ordering::Relaxed), Ordering::Relaxed);
+                    if rumbled != rumbling {
+                        rumbling = rumbled;
+                        println!("Rumble {}", if rumbling { "on" } else { "off" });
+                    }
                 }
 
+                let speed = emulation_speed.current();
+
+                // Above real time, only hand over as many frames as the display can show
+                if speed == Some(1.0) || last_presented.elapsed() >= display_period {
+                    last_presented = Instant::now();
+                    let mut pixels = vec![0; (WIDTH * HEIGHT * 4) as usize];
+                    gameboy.ppu.copy_to_framebuffer(&mut pixels);
+
+                    if tx_pixels.send(pixels).is_err() {
+                        break;
+                    }
+                }
+
+                // Audio is muted while rewinding and dropped when unthrottled
                 let available = gameboy.apu.channel2.buffer.samples_avail();
+                frames_without_audio += 1;
                 if available > 0 {
                     let mut samples = vec![0i16; available as usize];
                     gameboy.apu.channel2.buffer.read_samples(&mut samples, false);
-                    println!("Samples: {:?}", samples.iter().take(10).collect::<Vec<_>>());
-                    let samples_f32: Vec<f32> = samples.iter().map(|s| *s as f32 / 32768.0).collect();
-                    if tx_audio.send(samples_f32).is_err() {
-                        break
+                    if let Some(speed) = speed
+                        && !rewinding
+                    {
+                        audio.push(&resample(&samples, speed));
+                        frames_without_audio = 0;
                     }
                 }
 
-                let elapsed = start_time.elapsed();
-                if elapsed < frame_duration {
-                    thread::sleep(frame_duration - elapsed);
+                match sync {
+                    SyncMode::Audio if frames_without_audio < AUDIO_IDLE_FRAMES => {
+                        audio.wait_below(TARGET_LEVEL, display_period * AUDIO_IDLE_FRAMES);
+                    }
+                    SyncMode::Audio => limiter.wait(speed),
+                    SyncMode::Video => {
+                        gameboy.apu.set_output_rate(adjusted_rate(audio.level()));
+                        limiter.wait(speed);
+                    }
                 }
             }
-        });
-
-        let surface_texture = SurfaceTexture::new(WIDTH, HEIGHT, window);
-        let pixels =
-            Pixels::new(WIDTH, HEIGHT, surface_texture).expect("Failed to create pixels context");
 
-        Self {
-            pixels,
-            rx_pixels,
-            tx_inputs,
-            _window: window,
-            input_buffer: 0xFF,
-        }
+            finish_recording(&mut recording);
+            if let Some(data) = gameboy.battery()
+                && let Err(error) = battery::store(&rom_path, &data)
+            {
+                eprintln!("Failed to write {}: {error}", battery::save_path(&rom_path).display());
+            }
+            gameboy.disconnect_serial()
+        }));
     }
 
     pub(crate) fn update(&mut self) {
-        if let Ok(new_pixels) = self.rx_pixels.try_recv() {
+        for line in self.rx_console.try_iter() {
+            self.send_command(Command::Console(line));
+        }
+        if let Some(new_pixels) = self.rx_pixels.try_iter().last() {
             self.pixels.frame_mut().copy_from_slice(&new_pixels);
         }
     }
@@ -104,27 +415,69 @@ impl<'a> EmulatorApp<'a> {
     }
 
     pub(crate) fn update_inputs(&mut self, keycode: PhysicalKey, state: ElementState) {
-        match keycode {
-            PhysicalKey::Code(KeyCode::ArrowRight) | PhysicalKey::Code(KeyCode::KeyD) => {
-                self.set_input_state(0b0000_0001, state)
-            } // Right
-            PhysicalKey::Code(KeyCode::ArrowLeft) | PhysicalKey::Code(KeyCode::KeyA) => {
-                self.set_input_state(0b0000_0010, state)
-            } // Left
-            PhysicalKey::Code(KeyCode::ArrowUp) | PhysicalKey::Code(KeyCode::KeyW) => {
-                self.set_input_state(0b0000_0100, state)
-            } // Up
-            PhysicalKey::Code(KeyCode::ArrowDown) | PhysicalKey::Code(KeyCode::KeyS) => {
-                self.set_input_state(0b0000_1000, state)
-            } // Down
-            PhysicalKey::Code(KeyCode::KeyZ) => self.set_input_state(0b0001_0000, state), // A
-            PhysicalKey::Code(KeyCode::KeyX) => self.set_input_state(0b0010_0000, state), // B
-            PhysicalKey::Code(KeyCode::ShiftRight) => self.set_input_state(0b0100_0000, state), // Select
-            PhysicalKey::Code(KeyCode::Enter) => self.set_input_state(0b1000_0000, state), // Start
+        if let Some(mask) = self.keymap.action(keycode).and_then(Action::button_mask) {
+            self.set_input_state(mask, state);
+        }
+    }
+
+    pub(crate) fn handle_hotkey(&mut self, keycode: PhysicalKey, state: ElementState, repeat: bool) {
+        let Some(action) = self.keymap.action(keycode) else {
+            return;
+        };
+        let pressed = state.is_pressed();
+        match action {
+            Action::Rewind => self.rewinding.store(pressed, Ordering::Relaxed),
+            Action::FastForward => self.speed.set_fast_forward(pressed),
+            Action::FrameAdvance if pressed => self.send_command(Command::FrameAdvance),
+            Action::TiltLeft | Action::TiltRight | Action::TiltUp | Action::TiltDown => {
+                let index = action as usize - Action::TiltLeft as usize;
+                self.tilt_keys[index] = pressed;
+                self.send_tilt();
+            }
+            _ if !pressed || repeat => (),
+            Action::Turbo => self.speed.toggle_turbo(),
+            Action::Unthrottled => self.speed.toggle_unthrottled(),
+            Action::Pause => self.send_command(Command::TogglePause),
+            Action::SoftReset => self.send_command(Command::SoftReset),
+            Action::PowerCycle => self.send_command(Command::PowerCycle),
+            Action::SaveState => self.send_command(Command::SaveState),
+            Action::LoadState => self.send_command(Command::LoadState),
+            Action::RecordMovie => self.send_command(Command::ToggleRecording),
+            Action::ToggleCheats => self.send_command(Command::ToggleCheats),
             _ => (),
         }
     }
 
+    pub(crate) fn cursor_moved(&mut self, position: PhysicalPosition<f64>) {
+        let size = self.window.inner_size();
+        let fraction = |position: f64, size: u32| (position / size.max(1) as f64 * 2.0 - 1.0).clamp(-1.0, 1.0) as f32;
+        self.cursor = (fraction(position.x, size.width), fraction(position.y, size.height));
+        if self.mouse_tilt {
+            self.send_tilt();
+        }
+    }
+
+    pub(crate) fn mouse_input(&mut self, button: MouseButton, state: ElementState) {
+        if button == MouseButton::Left {
+            self.mouse_tilt = state.is_pressed();
+            self.send_tilt();
+        }
+    }
+
+    fn send_tilt(&self) {
+        let (x, y) = if self.mouse_tilt {
+            self.cursor
+        } else {
+            let [left, right, up, down] = self.tilt_keys.map(|held| held as u8 as f32 * KEY_TILT);
+            (right - left, down - up)
+        };
+        self.send_command(Command::Tilt(x, y));
+    }
+
+    fn send_command(&self, command: Command) {
+        self.tx_control.send(command).unwrap();
+    }
+
     fn set_input_state(&mut self, mask: u8, state: ElementState) {
         if state.is_pressed() {
             self.input_buffer &= !mask;
@@ -134,3 +487,40 @@ impl<'a> EmulatorApp<'a> {
         self.tx_inputs.send(self.input_buffer).unwrap();
     }
 }
+
+/// A fresh machine with the ROM inserted, its battery RAM restored from disk and its
+/// cheats applied.
+fn load_gameboy(rom_path: &Path) -> Result<Gameboy, CartridgeError> {
+    let mut gameboy = Gameboy::new();
+    gameboy.cartridge_to_rom(rom_path.display().to_string())?;
+    match cheat_file::load(rom_path) {
+        Ok(cheats) => cheats.into_iter().for_each(|cheat| gameboy.add_cheat(cheat)),
+        Err(error) => eprintln!("{}: {error}", cheat_file::path(rom_path).display()),
+    }
+    if !gameboy.cheats().is_empty() {
+        println!("Loaded {} cheats", gameboy.cheats().len());
+    }
+    Ok(gameboy)
+}
+
+fn load_battery(gameboy: &mut Gameboy, rom_path: &Path) {
+    if gameboy.battery().is_some()
+        && let Some(data) = battery::load(rom_path)
+    {
+        gameboy.load_battery(&data);
+    }
+}
+
+/// Recordings started from the hotkey go next to the ROM.
+fn movie_path(rom_path: &Path) -> PathBuf {
+    rom_path.with_extension("gbm")
+}
+
+fn finish_recording(recording: &mut Option<(PathBuf, Movie)>) {
+    if let Some((path, movie)) = recording.take() {
+        match fs::write(&path, movie.to_bytes()) {
+            Ok(()) => println!("Saved {} frames to {}", movie.inputs.len(), path.display()),
+            Err(error) => eprintln!("Failed to write {}: {error}", path.display()),
+        }
+    }
+}
@@ -0,0 +1,311 @@
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use winit::keyboard::{KeyCode, PhysicalKey};
+
+/// Everything a key can be bound to. The names are the keys of the config file.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Right,
+    Left,
+    Up,
+    Down,
+    A,
+    B,
+    Select,
+    Start,
+    Rewind,
+    FastForward,
+    Turbo,
+    Unthrottled,
+    Pause,
+    FrameAdvance,
+    SoftReset,
+    PowerCycle,
+    SaveState,
+    LoadState,
+    RecordMovie,
+    ToggleCheats,
+    TiltLeft,
+    TiltRight,
+    TiltUp,
+    TiltDown,
+}
+
+impl Action {
+    /// The bit of the joypad byte this button clears while held, `None` for hotkeys.
+    pub fn button_mask(self) -> Option<u8> {
+        match self {
+            Action::Right => Some(0b0000_0001),
+            Action::Left => Some(0b0000_0010),
+            Action::Up => Some(0b0000_0100),
+            Action::Down => Some(0b0000_1000),
+            Action::A => Some(0b0001_0000),
+            Action::B => Some(0b0010_0000),
+            Action::Select => Some(0b0100_0000),
+            Action::Start => Some(0b1000_0000),
+            _ => None,
+        }
+    }
+}
+
+const DEFAULT_BINDINGS: &[(Action, &[KeyCode])] = &[
+    (Action::Right, &[KeyCode::ArrowRight, KeyCode::KeyD]),
+    (Action::Left, &[KeyCode::ArrowLeft, KeyCode::KeyA]),
+    (Action::Up, &[KeyCode::ArrowUp, KeyCode::KeyW]),
+    (Action::Down, &[KeyCode::ArrowDown, KeyCode::KeyS]),
+    (Action::A, &[KeyCode::KeyZ]),
+    (Action::B, &[KeyCode::KeyX]),
+    (Action::Select, &[KeyCode::ShiftRight]),
+    (Action::Start, &[KeyCode::Enter]),
+    (Action::Rewind, &[KeyCode::Backspace]),
+    (Action::FastForward, &[KeyCode::Tab]),
+    (Action::Turbo, &[KeyCode::KeyT]),
+    (Action::Unthrottled, &[KeyCode::KeyU]),
+    (Action::Pause, &[KeyCode::KeyP]),
+    (Action::FrameAdvance, &[KeyCode::KeyN]),
+    (Action::SoftReset, &[KeyCode::F5]),
+    (Action::PowerCycle, &[KeyCode::F6]),
+    (Action::SaveState, &[KeyCode::F1]),
+    (Action::LoadState, &[KeyCode::F2]),
+    (Action::RecordMovie, &[KeyCode::F9]),
+    (Action::ToggleCheats, &[KeyCode::F10]),
+    (Action::TiltLeft, &[KeyCode::Numpad4]),
+    (Action::TiltRight, &[KeyCode::Numpad6]),
+    (Action::TiltUp, &[KeyCode::Numpad8]),
+    (Action::TiltDown, &[KeyCode::Numpad2]),
+];
+
+/// One set of bindings; an action listed here replaces all of its default keys, and an
+/// empty list unbinds it.
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct Layer {
+    buttons: BTreeMap<Action, Vec<KeyCode>>,
+    hotkeys: BTreeMap<Action, Vec<KeyCode>>,
+}
+
+/// The config file: global bindings plus `[rom."<title or SHA-1>"]` overrides on top.
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct ConfigFile {
+    buttons: BTreeMap<Action, Vec<KeyCode>>,
+    hotkeys: BTreeMap<Action, Vec<KeyCode>>,
+    rom: BTreeMap<String, Layer>,
+}
+
+#[derive(Debug)]
+pub enum KeymapError {
+    Io(io::Error),
+    Parse(toml::de::Error),
+    /// A hotkey in `[buttons]` or a button in `[hotkeys]`.
+    WrongTable {
+        action: Action,
+        table: String,
+    },
+    /// Keys bound to more than one action.
+    Conflicts(Vec<(KeyCode, Action, Action)>),
+}
+
+impl fmt::Display for KeymapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeymapError::Io(error) => write!(f, "Failed to read key bindings: {error}"),
+            KeymapError::Parse(error) => write!(f, "Invalid key bindings: {error}"),
+            KeymapError::WrongTable { action, table } => {
+                write!(f, "{action:?} does not belong in [{table}]")
+            }
+            KeymapError::Conflicts(conflicts) => {
+                write!(f, "Conflicting key bindings:")?;
+                for (key, first, second) in conflicts {
+                    write!(f, "\n  {key:?} is bound to both {first:?} and {second:?}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Error for KeymapError {}
+
+pub struct Keymap {
+    bindings: HashMap<KeyCode, Action>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Keymap::resolve(BTreeMap::new()).expect("Default key bindings conflict")
+    }
+}
+
+impl Keymap {
+    /// `<config dir>/gameboy/keys.toml`, e.g. `~/.config/gameboy/keys.toml` on Linux.
+    pub fn config_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("gameboy").join("keys.toml"))
+    }
+
+    /// Reads the user's bindings for the ROM with the given title and SHA-1. A missing
+    /// config file means the defaults.
+    pub fn load(title: &str, sha1: &str) -> Result<Self, KeymapError> {
+        let Some(path) = Keymap::config_path() else {
+            return Ok(Keymap::default());
+        };
+        match fs::read_to_string(path) {
+            Ok(text) => Keymap::parse(&text, title, sha1),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(Keymap::default()),
+            Err(error) => Err(KeymapError::Io(error)),
+        }
+    }
+
+    pub fn parse(text: &str, title: &str, sha1: &str) -> Result<Self, KeymapError> {
+        let config: ConfigFile = toml::from_str(text).map_err(KeymapError::Parse)?;
+
+        let mut layers = vec![(
+            "".to_string(),
+            Layer {
+                buttons: config.buttons,
+                hotkeys: config.hotkeys,
+            },
+        )];
+        layers.extend(config.rom);
+
+        let mut overrides = BTreeMap::new();
+        for (rom, layer) in layers {
+            // Every override is checked, even ones for other ROMs
+            let prefix = if rom.is_empty() {
+                String::new()
+            } else {
+                format!("rom.\"{rom}\".")
+            };
+            for (table, actions, buttons) in [
+                ("buttons", &layer.buttons, true),
+                ("hotkeys", &layer.hotkeys, false),
+            ] {
+                if let Some(action) = actions
+                    .keys()
+                    .find(|action| action.button_mask().is_some() != buttons)
+                {
+                    return Err(KeymapError::WrongTable {
+                        action: *action,
+                        table: format!("{prefix}{table}"),
+                    });
+                }
+            }
+
+            if rom.is_empty() || rom == title || rom.eq_ignore_ascii_case(sha1) {
+                overrides.extend(layer.buttons);
+                overrides.extend(layer.hotkeys);
+            }
+        }
+
+        Keymap::resolve(overrides)
+    }
+
+    fn resolve(overrides: BTreeMap<Action, Vec<KeyCode>>) -> Result<Self, KeymapError> {
+        let mut actions: BTreeMap<Action, Vec<KeyCode>> = DEFAULT_BINDINGS
+            .iter()
+            .map(|(action, keys)| (*action, keys.to_vec()))
+            .collect();
+        actions.extend(overrides);
+
+        let mut bindings = HashMap::new();
+        let mut conflicts = Vec::new();
+        for (action, keys) in actions {
+            for key in keys {
+                if let Some(&other) = bindings.get(&key)
+                    && other != action
+                {
+                    conflicts.push((key, other, action));
+                }
+                bindings.entry(key).or_insert(action);
+            }
+        }
+
+        if conflicts.is_empty() {
+            Ok(Keymap { bindings })
+        } else {
+            Err(KeymapError::Conflicts(conflicts))
+        }
+    }
+
+    pub fn action(&self, key: PhysicalKey) -> Option<Action> {
+        match key {
+            PhysicalKey::Code(code) => self.bindings.get(&code).copied(),
+            PhysicalKey::Unidentified(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn action(keymap: &Keymap, key: KeyCode) -> Option<Action> {
+        keymap.action(PhysicalKey::Code(key))
+    }
+
+    #[test]
+    fn overrides_replace_default_keys() {
+        let text = r#"
+            [buttons]
+            a = ["KeyK"]
+            b = []
+
+            [hotkeys]
+            pause = ["Space"]
+
+            [rom."TETRIS".buttons]
+            a = ["KeyJ"]
+        "#;
+        let keymap = Keymap::parse(text, "ZELDA", "00ff").unwrap();
+        assert_eq!(action(&keymap, KeyCode::KeyK), Some(Action::A));
+        assert_eq!(action(&keymap, KeyCode::KeyZ), None);
+        assert_eq!(action(&keymap, KeyCode::KeyX), None);
+        assert_eq!(action(&keymap, KeyCode::Space), Some(Action::Pause));
+        assert_eq!(action(&keymap, KeyCode::ArrowUp), Some(Action::Up));
+
+        let keymap = Keymap::parse(text, "TETRIS", "00ff").unwrap();
+        assert_eq!(action(&keymap, KeyCode::KeyJ), Some(Action::A));
+        assert_eq!(action(&keymap, KeyCode::KeyK), None);
+
+        let text = "[rom.00FF.hotkeys]\nturbo = [\"KeyY\"]";
+        let keymap = Keymap::parse(text, "TETRIS", "00ff").unwrap();
+        assert_eq!(action(&keymap, KeyCode::KeyY), Some(Action::Turbo));
+    }
+
+    #[test]
+    fn invalid_bindings_are_reported() {
+        let conflict = "[buttons]\nstart = [\"KeyP\"]";
+        match Keymap::parse(conflict, "", "") {
+            Err(KeymapError::Conflicts(conflicts)) => {
+                assert_eq!(
+                    conflicts,
+                    vec![(KeyCode::KeyP, Action::Start, Action::Pause)]
+                );
+            }
+            _ => panic!("conflict not detected"),
+        }
+
+        let misplaced = "[rom.OTHER.buttons]\nrewind = [\"KeyR\"]";
+        assert!(matches!(
+            Keymap::parse(misplaced, "", ""),
+            Err(KeymapError::WrongTable {
+                action: Action::Rewind,
+                ..
+            })
+        ));
+        assert!(matches!(
+            Keymap::parse("[buttons]\na = [\"NoSuchKey\"]", "", ""),
+            Err(KeymapError::Parse(_))
+        ));
+        assert!(matches!(
+            Keymap::parse("[buttons]\njump = [\"Space\"]", "", ""),
+            Err(KeymapError::Parse(_))
+        ));
+    }
+}
@@ -0,0 +1,175 @@
+use std::collections::VecDeque;
+
+/// Save states of the recent past, newest last. Only the newest is kept whole; every
+/// older one is stored as the XOR against its successor, run-length encoded, so
+/// frames that barely differ cost a few hundred bytes.
+pub struct RewindBuffer {
+    latest: Option<Vec<u8>>,
+    /// `deltas[i]` turns state `i + 1` (or `latest` for the last one) back into state `i`.
+    deltas: VecDeque<Vec<u8>>,
+    used: usize,
+    budget: usize,
+}
+
+impl RewindBuffer {
+    pub fn new(budget: usize) -> Self {
+        RewindBuffer {
+            latest: None,
+            deltas: VecDeque::new(),
+            used: 0,
+            budget,
+        }
+    }
+
+    pub fn push(&mut self, state: Vec<u8>) {
+        if let Some(previous) = self.latest.take() {
+            self.used -= previous.len();
+            if previous.len() == state.len() {
+                let delta = encode_delta(&state, &previous);
+                self.used += delta.len();
+                self.deltas.push_back(delta);
+            } else {
+                // The machine changed shape (e.g. another cartridge), older states are useless
+                self.clear();
+            }
+        }
+        self.used += state.len();
+        self.latest = Some(state);
+
+        while self.used > self.budget {
+            match self.deltas.pop_front() {
+                Some(delta) => self.used -= delta.len(),
+                None => break,
+            }
+        }
+    }
+
+    /// Removes and returns the newest state.
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        let latest = self.latest.take()?;
+        self.used -= latest.len();
+        if let Some(delta) = self.deltas.pop_back() {
+            self.used -= delta.len();
+            let previous = decode_delta(&latest, &delta);
+            self.used += previous.len();
+            self.latest = Some(previous);
+        }
+        Some(latest)
+    }
+
+    pub fn clear(&mut self) {
+        self.latest = None;
+        self.deltas.clear();
+        self.used = 0;
+    }
+}
+
+/// Encodes `target` relative to `base` (same length) as pairs of
+/// (unchanged run length, changed run length) followed by the changed bytes XORed.
+fn encode_delta(base: &[u8], target: &[u8]) -> Vec<u8> {
+    let mut delta = Vec::new();
+    let mut index = 0;
+    while index < target.len() {
+        let unchanged = base[index..]
+            .iter()
+            .zip(&target[index..])
+            .take_while(|(a, b)| a == b)
+            .count();
+        index += unchanged;
+        let changed = base[index..]
+            .iter()
+            .zip(&target[index..])
+            .take_while(|(a, b)| a != b)
+            .count();
+        write_varint(&mut delta, unchanged);
+        write_varint(&mut delta, changed);
+        delta.extend((index..index + changed).map(|i| base[i] ^ target[i]));
+        index += changed;
+    }
+    delta
+}
+
+fn decode_delta(base: &[u8], delta: &[u8]) -> Vec<u8> {
+    let mut target = base.to_vec();
+    let mut index = 0;
+    let mut position = 0;
+    while position < delta.len() {
+        index += read_varint(delta, &mut position);
+        let changed = read_varint(delta, &mut position);
+        for byte in &delta[position..position + changed] {
+            target[index] ^= byte;
+            index += 1;
+        }
+        position += changed;
+    }
+    target
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(data: &[u8], position: &mut usize) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = data[*position];
+        *position += 1;
+        value |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(seed: u8) -> Vec<u8> {
+        let mut state = vec![0u8; 0x4000];
+        for (i, byte) in state.iter_mut().enumerate().step_by(97) {
+            *byte = seed.wrapping_mul(31).wrapping_add(i as u8);
+        }
+        state
+    }
+
+    #[test]
+    fn delta_round_trips() {
+        let base = state(1);
+        let target = state(2);
+        let delta = encode_delta(&base, &target);
+        assert!(delta.len() < target.len() / 4);
+        assert_eq!(decode_delta(&base, &delta), target);
+        assert_eq!(decode_delta(&base, &encode_delta(&base, &base)), base);
+    }
+
+    #[test]
+    fn pops_newest_first() {
+        let mut buffer = RewindBuffer::new(usize::MAX);
+        for seed in 0..10 {
+            buffer.push(state(seed));
+        }
+        for seed in (0..10).rev() {
+            assert_eq!(buffer.pop(), Some(state(seed)));
+        }
+        assert_eq!(buffer.pop(), None);
+    }
+
+    #[test]
+    fn drops_oldest_states_over_budget() {
+        let mut buffer = RewindBuffer::new(0x4000 + 2000);
+        for seed in 0..100 {
+            buffer.push(state(seed));
+        }
+        assert!(buffer.used <= buffer.budget);
+        assert_eq!(buffer.pop(), Some(state(99)));
+        assert_eq!(buffer.pop(), Some(state(98)));
+        assert!(buffer.deltas.len() < 97);
+    }
+}
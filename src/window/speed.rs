@@ -0,0 +1,90 @@
+use crate::components::gameboy::{CLOCK_RATE, CYCLES_PER_FRAME};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// 4194304 / 70224, about 59.73 frames per second.
+pub const FRAME_RATE: f64 = CLOCK_RATE as f64 / CYCLES_PER_FRAME as f64;
+
+/// Falling further behind than this many frames resets the schedule instead of
+/// running flat out to catch up.
+const MAX_LAG_FRAMES: u32 = 4;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SyncMode {
+    /// Frames are paced by the clock; the audio rate follows.
+    Video,
+    /// Frames are paced by how fast the audio device drains its queue.
+    Audio,
+}
+
+/// Emulation speed as chosen from the window thread.
+pub struct Speed {
+    fast_forward: AtomicBool,
+    turbo: AtomicBool,
+    unthrottled: AtomicBool,
+    multiplier: f64,
+}
+
+impl Speed {
+    pub fn new(multiplier: f64) -> Self {
+        Speed {
+            fast_forward: AtomicBool::new(false),
+            turbo: AtomicBool::new(false),
+            unthrottled: AtomicBool::new(false),
+            multiplier,
+        }
+    }
+
+    pub fn set_fast_forward(&self, held: bool) {
+        self.fast_forward.store(held, Ordering::Relaxed);
+    }
+
+    pub fn toggle_turbo(&self) {
+        self.turbo.fetch_xor(true, Ordering::Relaxed);
+    }
+
+    pub fn toggle_unthrottled(&self) {
+        self.unthrottled.fetch_xor(true, Ordering::Relaxed);
+    }
+
+    /// The current speed as a multiple of real time, or `None` when unthrottled.
+    pub fn current(&self) -> Option<f64> {
+        if self.unthrottled.load(Ordering::Relaxed) {
+            None
+        } else if self.fast_forward.load(Ordering::Relaxed) || self.turbo.load(Ordering::Relaxed) {
+            Some(self.multiplier)
+        } else {
+            Some(1.0)
+        }
+    }
+}
+
+/// Paces frames against a running deadline so sleep overshoot doesn't accumulate.
+pub struct FrameLimiter {
+    next_frame: Instant,
+}
+
+impl FrameLimiter {
+    pub fn new() -> Self {
+        FrameLimiter {
+            next_frame: Instant::now(),
+        }
+    }
+
+    pub fn wait(&mut self, speed: Option<f64>) {
+        let now = Instant::now();
+        let Some(speed) = speed else {
+            self.next_frame = now;
+            return;
+        };
+
+        let period = Duration::from_secs_f64(1.0 / (FRAME_RATE * speed));
+        self.next_frame += period;
+        if self.next_frame > now {
+            thread::sleep(self.next_frame - now);
+        } else if now - self.next_frame > period * MAX_LAG_FRAMES {
+            self.next_frame = now;
+        }
+    }
+}